@@ -4,7 +4,7 @@ use coinbase_api::prelude::*;
 async fn main() {
     let auth = CoinbaseAuth::ApiKeys {
         api_key: String::from("<api-key>"),
-        secret_key: String::from("<secret-key>"),
+        secret_key: SecretString::from("<secret-key>"),
     };
     let client = CoinbaseAppClient::new(auth).unwrap();
 
@@ -0,0 +1,22 @@
+use coinbase_api::prelude::*;
+use futures_util::StreamExt;
+
+#[tokio::main]
+async fn main() {
+    let client = WebSocketClient::connect(CoinbaseAuth::None).await.unwrap();
+
+    let mut stream = client.product_stream("BTC-USD");
+
+    client
+        .subscribe(Channel::Heartbeats, Vec::<String>::new())
+        .await
+        .unwrap();
+    client
+        .subscribe(Channel::Ticker, ["BTC-USD"])
+        .await
+        .unwrap();
+
+    while let Some(notification) = stream.next().await {
+        println!("{:#?}", notification);
+    }
+}
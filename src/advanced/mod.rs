@@ -0,0 +1,5 @@
+//! Coinbase Advanced Trade APIs
+//!
+//! <https://docs.cdp.coinbase.com/advanced-trade/docs/welcome>
+
+pub mod ws;
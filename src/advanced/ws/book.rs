@@ -0,0 +1,393 @@
+//! Local level 2 order book
+//!
+//! <https://docs.cdp.coinbase.com/advanced-trade/docs/ws-channels#level2-channel>
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use super::channel::Channel;
+use super::client::WebSocketClient;
+use super::error::Error;
+use super::message::{Event, EventType, Level2Event, Notification, Side, TradeSide};
+
+/// Price level key, ordered with [`f64::total_cmp`].
+#[derive(Debug, Clone, Copy)]
+struct Price(f64);
+
+impl PartialEq for Price {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Price {}
+
+impl PartialOrd for Price {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Price {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// Outcome of [`OrderBook::apply`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BookUpdate {
+    /// The notification doesn't concern the book
+    Ignored,
+    /// A snapshot has been applied: the book is in sync
+    Snapshot,
+    /// An update has been applied
+    Updated,
+    /// The book is out of sync and a new snapshot must be requested (see [`OrderBook::resnapshot`])
+    ResnapshotRequired,
+    /// The book is out of sync, waiting for the snapshot sent after the re-subscription
+    AwaitingSnapshot,
+}
+
+/// Local level 2 order book of a product
+///
+/// The book must be fed with **all** the notifications of the connection (see [`WebSocketClient::notifications`]),
+/// since the sequence numbers are shared by all the channels and products.
+#[derive(Debug, Clone)]
+pub struct OrderBook {
+    product_id: String,
+    bids: BTreeMap<Price, f64>,
+    asks: BTreeMap<Price, f64>,
+    last_sequence_num: Option<u64>,
+    synced: bool,
+}
+
+impl OrderBook {
+    /// New empty order book
+    pub fn new<S>(product_id: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            product_id: product_id.into(),
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+            last_sequence_num: None,
+            synced: false,
+        }
+    }
+
+    /// Product ID
+    #[inline]
+    pub fn product_id(&self) -> &str {
+        &self.product_id
+    }
+
+    /// Check if the book reflects the exchange state
+    #[inline]
+    pub fn is_synced(&self) -> bool {
+        self.synced
+    }
+
+    /// Apply a notification
+    pub fn apply(&mut self, notification: &Notification) -> BookUpdate {
+        match notification {
+            Notification::Message(message) => {
+                // Validate the sequence number
+                let mut gap: bool = false;
+
+                if let Some(last) = self.last_sequence_num {
+                    // Stale or duplicated message
+                    if message.sequence_num <= last {
+                        return BookUpdate::Ignored;
+                    }
+
+                    if message.sequence_num > last + 1 && self.synced {
+                        self.reset();
+                        gap = true;
+                    }
+                }
+
+                self.last_sequence_num = Some(message.sequence_num);
+
+                let mut outcome: BookUpdate = BookUpdate::Ignored;
+
+                for event in message.events.iter() {
+                    if let Event::Level2(event) = event {
+                        if event.product_id == self.product_id {
+                            outcome = self.apply_event(event);
+                        }
+                    }
+                }
+
+                // A snapshot in the message brings the book back in sync
+                if gap && !self.synced {
+                    return BookUpdate::ResnapshotRequired;
+                }
+
+                outcome
+            }
+            // The subscriptions are restored after a reconnection, so a snapshot is coming.
+            Notification::Disconnected(..) => {
+                self.last_sequence_num = None;
+                self.reset();
+                BookUpdate::AwaitingSnapshot
+            }
+            Notification::Resync(..) | Notification::Error(..) => BookUpdate::Ignored,
+        }
+    }
+
+    fn apply_event(&mut self, event: &Level2Event) -> BookUpdate {
+        match event.r#type {
+            EventType::Snapshot => {
+                self.bids.clear();
+                self.asks.clear();
+                self.synced = true;
+            }
+            EventType::Update if !self.synced => return BookUpdate::AwaitingSnapshot,
+            EventType::Update => {}
+        }
+
+        for update in event.updates.iter() {
+            let levels = match update.side {
+                Side::Bid => &mut self.bids,
+                Side::Offer => &mut self.asks,
+            };
+
+            let price = Price(update.price_level);
+
+            if update.new_quantity > 0.0 {
+                levels.insert(price, update.new_quantity);
+            } else {
+                levels.remove(&price);
+            }
+        }
+
+        match event.r#type {
+            EventType::Snapshot => BookUpdate::Snapshot,
+            EventType::Update => BookUpdate::Updated,
+        }
+    }
+
+    /// Clear the book and wait for a new snapshot
+    fn reset(&mut self) {
+        self.bids.clear();
+        self.asks.clear();
+        self.synced = false;
+    }
+
+    /// Request a new snapshot, by re-subscribing to the `level2` channel for the product
+    pub async fn resnapshot(&mut self, client: &WebSocketClient) -> Result<(), Error> {
+        self.reset();
+
+        client
+            .unsubscribe(Channel::Level2, [self.product_id.as_str()])
+            .await?;
+        client
+            .subscribe(Channel::Level2, [self.product_id.as_str()])
+            .await
+    }
+
+    /// Bids, from the best (highest) price: `(price, quantity)`
+    pub fn bids(&self) -> impl Iterator<Item = (f64, f64)> + '_ {
+        self.bids.iter().rev().map(|(price, qty)| (price.0, *qty))
+    }
+
+    /// Asks, from the best (lowest) price: `(price, quantity)`
+    pub fn asks(&self) -> impl Iterator<Item = (f64, f64)> + '_ {
+        self.asks.iter().map(|(price, qty)| (price.0, *qty))
+    }
+
+    /// Best bid: `(price, quantity)`
+    #[inline]
+    pub fn best_bid(&self) -> Option<(f64, f64)> {
+        self.bids().next()
+    }
+
+    /// Best ask: `(price, quantity)`
+    #[inline]
+    pub fn best_ask(&self) -> Option<(f64, f64)> {
+        self.asks().next()
+    }
+
+    /// Quantity available at a price level
+    pub fn depth_at(&self, side: Side, price: f64) -> f64 {
+        let levels = match side {
+            Side::Bid => &self.bids,
+            Side::Offer => &self.asks,
+        };
+
+        levels.get(&Price(price)).copied().unwrap_or_default()
+    }
+
+    /// Difference between the best ask and the best bid
+    pub fn spread(&self) -> Option<f64> {
+        let (bid, _) = self.best_bid()?;
+        let (ask, _) = self.best_ask()?;
+        Some(ask - bid)
+    }
+
+    /// Average of the best ask and the best bid
+    pub fn mid_price(&self) -> Option<f64> {
+        let (bid, _) = self.best_bid()?;
+        let (ask, _) = self.best_ask()?;
+        Some((ask + bid) / 2.0)
+    }
+
+    /// Volume-weighted average price to fill a market order of the provided quantity
+    ///
+    /// A buy walks the asks, a sell walks the bids.
+    /// Returns `None` if the quantity is not positive or the book doesn't have enough liquidity.
+    pub fn vwap(&self, side: TradeSide, quantity: f64) -> Option<f64> {
+        if quantity <= 0.0 {
+            return None;
+        }
+
+        let levels: Box<dyn Iterator<Item = (f64, f64)>> = match side {
+            TradeSide::Buy => Box::new(self.asks()),
+            TradeSide::Sell => Box::new(self.bids()),
+        };
+
+        let mut remaining: f64 = quantity;
+        let mut notional: f64 = 0.0;
+
+        for (price, qty) in levels {
+            let filled: f64 = qty.min(remaining);
+            notional += filled * price;
+            remaining -= filled;
+
+            if remaining <= 0.0 {
+                return Some(notional / quantity);
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::advanced::ws::message::{Level2Update, Message};
+
+    fn level2(
+        sequence_num: u64,
+        r#type: EventType,
+        product_id: &str,
+        updates: &[(Side, f64, f64)],
+    ) -> Notification {
+        Notification::Message(Message {
+            client_id: String::new(),
+            timestamp: Utc::now(),
+            sequence_num,
+            events: vec![Event::Level2(Level2Event {
+                r#type,
+                product_id: product_id.to_string(),
+                updates: updates
+                    .iter()
+                    .map(|(side, price_level, new_quantity)| Level2Update {
+                        side: *side,
+                        event_time: Utc::now(),
+                        price_level: *price_level,
+                        new_quantity: *new_quantity,
+                    })
+                    .collect(),
+            })],
+        })
+    }
+
+    fn snapshot(sequence_num: u64) -> Notification {
+        level2(
+            sequence_num,
+            EventType::Snapshot,
+            "BTC-USD",
+            &[
+                (Side::Bid, 99.0, 1.0),
+                (Side::Bid, 98.0, 2.0),
+                (Side::Offer, 101.0, 1.0),
+                (Side::Offer, 102.0, 3.0),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_snapshot_and_updates() {
+        let mut book = OrderBook::new("BTC-USD");
+
+        // Updates before the snapshot are not applied
+        let update = level2(0, EventType::Update, "BTC-USD", &[(Side::Bid, 99.5, 1.0)]);
+        assert_eq!(book.apply(&update), BookUpdate::AwaitingSnapshot);
+
+        assert_eq!(book.apply(&snapshot(1)), BookUpdate::Snapshot);
+        assert!(book.is_synced());
+        assert_eq!(book.best_bid(), Some((99.0, 1.0)));
+        assert_eq!(book.best_ask(), Some((101.0, 1.0)));
+        assert_eq!(book.spread(), Some(2.0));
+        assert_eq!(book.mid_price(), Some(100.0));
+
+        let update = level2(
+            2,
+            EventType::Update,
+            "BTC-USD",
+            &[(Side::Bid, 100.0, 0.5), (Side::Offer, 101.0, 0.0)],
+        );
+        assert_eq!(book.apply(&update), BookUpdate::Updated);
+        assert_eq!(book.best_bid(), Some((100.0, 0.5)));
+        assert_eq!(book.best_ask(), Some((102.0, 3.0)));
+        assert_eq!(book.depth_at(Side::Bid, 98.0), 2.0);
+        assert_eq!(book.depth_at(Side::Offer, 101.0), 0.0);
+
+        // Other products are ignored
+        let update = level2(3, EventType::Update, "ETH-USD", &[(Side::Bid, 1.0, 1.0)]);
+        assert_eq!(book.apply(&update), BookUpdate::Ignored);
+        assert_eq!(book.bids().count(), 3);
+
+        // Stale messages are ignored
+        assert_eq!(book.apply(&snapshot(2)), BookUpdate::Ignored);
+        assert_eq!(book.best_bid(), Some((100.0, 0.5)));
+    }
+
+    #[test]
+    fn test_sequence_gap() {
+        let mut book = OrderBook::new("BTC-USD");
+        book.apply(&snapshot(0));
+
+        let update = level2(5, EventType::Update, "BTC-USD", &[(Side::Bid, 100.0, 1.0)]);
+        assert_eq!(book.apply(&update), BookUpdate::ResnapshotRequired);
+        assert!(!book.is_synced());
+        assert_eq!(book.best_bid(), None);
+
+        let update = level2(6, EventType::Update, "BTC-USD", &[(Side::Bid, 100.0, 1.0)]);
+        assert_eq!(book.apply(&update), BookUpdate::AwaitingSnapshot);
+
+        assert_eq!(book.apply(&snapshot(7)), BookUpdate::Snapshot);
+        assert!(book.is_synced());
+
+        // A snapshot received after a gap is applied
+        assert_eq!(book.apply(&snapshot(10)), BookUpdate::Snapshot);
+        assert!(book.is_synced());
+        assert_eq!(book.best_bid(), Some((99.0, 1.0)));
+
+        // Sequence numbers restart after a reconnection
+        let disconnected = Notification::Disconnected(String::new());
+        assert_eq!(book.apply(&disconnected), BookUpdate::AwaitingSnapshot);
+        assert_eq!(book.apply(&snapshot(0)), BookUpdate::Snapshot);
+    }
+
+    #[test]
+    fn test_vwap() {
+        let mut book = OrderBook::new("BTC-USD");
+        book.apply(&snapshot(0));
+
+        assert_eq!(book.vwap(TradeSide::Buy, 1.0), Some(101.0));
+        assert_eq!(book.vwap(TradeSide::Buy, 2.0), Some((101.0 + 102.0) / 2.0));
+        assert_eq!(
+            book.vwap(TradeSide::Sell, 3.0),
+            Some((99.0 + 2.0 * 98.0) / 3.0)
+        );
+        assert_eq!(book.vwap(TradeSide::Buy, 5.0), None);
+        assert_eq!(book.vwap(TradeSide::Sell, 0.0), None);
+    }
+}
@@ -0,0 +1,93 @@
+//! Advanced Trade WebSocket client builder
+
+use url::Url;
+
+use super::client::WebSocketClient;
+use super::constant::{DEFAULT_CAPACITY, WS_MARKET_DATA_URL, WS_USER_DATA_URL};
+use super::error::Error;
+use super::reconnect::ReconnectPolicy;
+use crate::app::auth::CoinbaseAuth;
+
+/// WebSocket endpoint
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Endpoint {
+    /// Market data
+    #[default]
+    MarketData,
+    /// User order data, with lower latency for the `user` and `futures_balance_summary` channels
+    UserData,
+    /// Custom endpoint
+    Custom(Url),
+}
+
+impl Endpoint {
+    pub(super) fn url(self) -> Result<Url, Error> {
+        match self {
+            Self::MarketData => Ok(Url::parse(WS_MARKET_DATA_URL)?),
+            Self::UserData => Ok(Url::parse(WS_USER_DATA_URL)?),
+            Self::Custom(url) => Ok(url),
+        }
+    }
+}
+
+/// Advanced Trade WebSocket client builder
+#[derive(Debug, Clone)]
+pub struct WebSocketClientBuilder {
+    /// Authentication, used to sign the subscriptions
+    pub auth: CoinbaseAuth,
+    /// WebSocket endpoint
+    pub endpoint: Endpoint,
+    /// Capacity of the notifications channel
+    pub capacity: usize,
+    /// Reconnection policy
+    pub reconnect: ReconnectPolicy,
+}
+
+impl Default for WebSocketClientBuilder {
+    fn default() -> Self {
+        Self {
+            auth: CoinbaseAuth::default(),
+            endpoint: Endpoint::default(),
+            capacity: DEFAULT_CAPACITY,
+            reconnect: ReconnectPolicy::default(),
+        }
+    }
+}
+
+impl WebSocketClientBuilder {
+    /// Set authentication
+    #[inline]
+    pub fn auth(mut self, auth: CoinbaseAuth) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    /// Set the WebSocket endpoint (default: market data)
+    #[inline]
+    pub fn endpoint(mut self, endpoint: Endpoint) -> Self {
+        self.endpoint = endpoint;
+        self
+    }
+
+    /// Set the capacity of the notifications channel (default: 1024)
+    ///
+    /// Slow consumers that fall behind by more than this will receive [`Error::Lagged`].
+    #[inline]
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Set the reconnection policy
+    #[inline]
+    pub fn reconnect(mut self, reconnect: ReconnectPolicy) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    /// Connect to the WebSocket endpoint
+    #[inline]
+    pub async fn connect(self) -> Result<WebSocketClient, Error> {
+        WebSocketClient::from_builder(self).await
+    }
+}
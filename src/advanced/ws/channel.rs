@@ -0,0 +1,68 @@
+//! Advanced Trade WebSocket channels
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// WebSocket channel
+///
+/// <https://docs.cdp.coinbase.com/advanced-trade/docs/ws-channels>
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Channel {
+    /// Real-time price updates every time a match happens
+    #[serde(rename = "ticker")]
+    Ticker,
+    /// Price updates batched every 5 seconds
+    #[serde(rename = "ticker_batch")]
+    TickerBatch,
+    /// Order book updates, guaranteed delivery of all updates
+    #[serde(rename = "level2")]
+    Level2,
+    /// Real-time updates every time a market trade happens
+    #[serde(rename = "market_trades")]
+    MarketTrades,
+    /// Real-time updates on product candles
+    #[serde(rename = "candles")]
+    Candles,
+    /// Sends all products and currencies on a preset interval
+    #[serde(rename = "status")]
+    Status,
+    /// Real-time server pings to keep all connections open
+    #[serde(rename = "heartbeats")]
+    Heartbeats,
+    /// Real-time updates on the orders of the user (authentication required)
+    #[serde(rename = "user")]
+    User,
+    /// Real-time updates on the futures balances of the user (authentication required)
+    #[serde(rename = "futures_balance_summary")]
+    FuturesBalanceSummary,
+}
+
+impl Channel {
+    /// Check if the channel requires authentication
+    #[inline]
+    pub fn is_authenticated(&self) -> bool {
+        matches!(self, Self::User | Self::FuturesBalanceSummary)
+    }
+
+    /// Name used in the subscription messages
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ticker => "ticker",
+            Self::TickerBatch => "ticker_batch",
+            Self::Level2 => "level2",
+            Self::MarketTrades => "market_trades",
+            Self::Candles => "candles",
+            Self::Status => "status",
+            Self::Heartbeats => "heartbeats",
+            Self::User => "user",
+            Self::FuturesBalanceSummary => "futures_balance_summary",
+        }
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
@@ -0,0 +1,344 @@
+//! Advanced Trade WebSocket client
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+
+use futures_util::StreamExt;
+use futures_util::stream::{self, BoxStream};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
+use url::Url;
+
+use super::builder::WebSocketClientBuilder;
+use super::channel::Channel;
+use super::connection::{Command, Connection};
+use super::error::Error;
+use super::message::Notification;
+use crate::app::auth::CoinbaseAuth;
+use crate::app::auth::jwt::JwtGenerator;
+
+/// Product IDs by channel
+pub type Subscriptions = BTreeMap<Channel, BTreeSet<String>>;
+
+/// Advanced Trade WebSocket client
+///
+/// The connection is kept alive in background: when it drops, the client reconnects according to the
+/// [`ReconnectPolicy`](super::reconnect::ReconnectPolicy), restores the subscriptions and emits a
+/// [`Resync`](super::message::Resync) notification.
+#[derive(Debug, Clone)]
+pub struct WebSocketClient {
+    /// Commands for the connection task.
+    commands: mpsc::UnboundedSender<Command>,
+    /// Decoded notifications.
+    notifications: broadcast::Sender<Notification>,
+    /// Active subscriptions.
+    subscriptions: Arc<Mutex<Subscriptions>>,
+    /// Closed when the connection task exits (i.e., the reconnection policy gave up).
+    closed: watch::Receiver<()>,
+    /// Whether the subscriptions are signed.
+    authenticated: bool,
+}
+
+impl WebSocketClient {
+    /// Connect to the market data endpoint
+    pub async fn connect(auth: CoinbaseAuth) -> Result<Self, Error> {
+        Self::builder().auth(auth).connect().await
+    }
+
+    /// Get a new builder
+    #[inline]
+    pub fn builder() -> WebSocketClientBuilder {
+        WebSocketClientBuilder::default()
+    }
+
+    pub(super) async fn from_builder(builder: WebSocketClientBuilder) -> Result<Self, Error> {
+        let jwt: Option<JwtGenerator> = match builder.auth {
+            CoinbaseAuth::None => None,
+            CoinbaseAuth::ApiKeys {
+                api_key,
+                secret_key,
+            } => Some(JwtGenerator::new(api_key, secret_key)?),
+            CoinbaseAuth::Signer(signer) => Some(JwtGenerator::with_signer(signer)),
+            CoinbaseAuth::Jwt(jwt) => Some(jwt),
+            CoinbaseAuth::OAuth2(..) => return Err(Error::UnsupportedAuthentication),
+        };
+
+        let authenticated: bool = jwt.is_some();
+        let url: Url = builder.endpoint.url()?;
+
+        // Connect before returning, to report the errors of the first attempt
+        let socket = Connection::connect(&url).await?;
+
+        let (commands, commands_rx) = mpsc::unbounded_channel();
+        let (notifications, _) = broadcast::channel(builder.capacity);
+        let subscriptions = Arc::new(Mutex::new(Subscriptions::new()));
+        let (closed_tx, closed) = watch::channel(());
+
+        let connection = Connection {
+            url,
+            jwt,
+            reconnect: builder.reconnect,
+            commands: commands_rx,
+            notifications: notifications.clone(),
+            subscriptions: subscriptions.clone(),
+            _closed: closed_tx,
+        };
+        tokio::spawn(connection.run(socket));
+
+        Ok(Self {
+            commands,
+            notifications,
+            subscriptions,
+            closed,
+            authenticated,
+        })
+    }
+
+    /// Subscribe to a channel
+    ///
+    /// Channels that aren't bound to a product (i.e., [`Channel::Heartbeats`]) accept an empty list.
+    ///
+    /// While reconnecting, the subscription is recorded and applied once the connection is restored.
+    ///
+    /// <https://docs.cdp.coinbase.com/advanced-trade/docs/ws-overview#subscribe>
+    pub async fn subscribe<I, S>(&self, channel: Channel, product_ids: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        if channel.is_authenticated() && !self.authenticated {
+            return Err(Error::AuthenticationRequired(channel));
+        }
+
+        let product_ids: Vec<String> = product_ids.into_iter().map(Into::into).collect();
+
+        self.send(|reply| Command::Subscribe {
+            channel,
+            product_ids,
+            reply,
+        })
+        .await
+    }
+
+    /// Unsubscribe from a channel
+    ///
+    /// An empty list unsubscribes from all the products of the channel.
+    pub async fn unsubscribe<I, S>(&self, channel: Channel, product_ids: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let product_ids: Vec<String> = product_ids.into_iter().map(Into::into).collect();
+
+        self.send(|reply| Command::Unsubscribe {
+            channel,
+            product_ids,
+            reply,
+        })
+        .await
+    }
+
+    /// Get the active subscriptions
+    pub fn subscriptions(&self) -> Subscriptions {
+        self.subscriptions
+            .lock()
+            .expect("subscriptions lock poisoned")
+            .clone()
+    }
+
+    /// Stream of all the notifications
+    ///
+    /// Each call returns an independent stream that only receives the notifications sent after its creation.
+    /// The stream ends when the connection is gone for good (i.e., the reconnection policy gave up).
+    pub fn notifications(&self) -> BoxStream<'static, Result<Notification, Error>> {
+        let receiver = self.notifications.subscribe();
+        let closed = self.closed.clone();
+
+        stream::unfold(
+            (receiver, closed),
+            |(mut receiver, mut closed)| async move {
+                let res = tokio::select! {
+                    // The pending notifications first
+                    biased;
+                    res = receiver.recv() => res,
+                    _ = closed.changed() => return None,
+                };
+
+                match res {
+                    Ok(notification) => Some((Ok(notification), (receiver, closed))),
+                    Err(RecvError::Lagged(skipped)) => {
+                        Some((Err(Error::Lagged(skipped)), (receiver, closed)))
+                    }
+                    Err(RecvError::Closed) => None,
+                }
+            },
+        )
+        .boxed()
+    }
+
+    /// Stream of the notifications related to a product
+    ///
+    /// Channel messages are trimmed to the events of the product,
+    /// while errors, connection notifications and the events that aren't bound to a product
+    /// (i.e., heartbeats) are always forwarded.
+    pub fn product_stream<S>(
+        &self,
+        product_id: S,
+    ) -> BoxStream<'static, Result<Notification, Error>>
+    where
+        S: Into<String>,
+    {
+        let product_id: String = product_id.into();
+
+        self.notifications()
+            .filter_map(move |notification| {
+                let notification = match notification {
+                    Ok(Notification::Message(mut message)) => message
+                        .retain_product(&product_id)
+                        .then_some(Ok(Notification::Message(message))),
+                    other => Some(other),
+                };
+
+                async move { notification }
+            })
+            .boxed()
+    }
+
+    async fn send<F>(&self, command: F) -> Result<(), Error>
+    where
+        F: FnOnce(oneshot::Sender<Result<(), Error>>) -> Command,
+    {
+        let (reply, rx) = oneshot::channel();
+        self.commands
+            .send(command(reply))
+            .map_err(|_| Error::Closed)?;
+        rx.await.map_err(|_| Error::Closed)?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+    use super::*;
+    use crate::advanced::ws::builder::Endpoint;
+    use crate::advanced::ws::message::Resync;
+    use crate::advanced::ws::reconnect::ReconnectPolicy;
+
+    #[tokio::test]
+    async fn test_reconnect_and_resubscribe() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("ws://{}", listener.local_addr().unwrap())).unwrap();
+
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+
+            // First connection: receive the subscription, then drop the connection
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+            requests.push(socket.next().await.unwrap().unwrap());
+            drop(socket);
+
+            // Second connection: receive the re-subscription
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+            requests.push(socket.next().await.unwrap().unwrap());
+
+            requests
+        });
+
+        let client = WebSocketClient::builder()
+            .endpoint(Endpoint::Custom(url))
+            .reconnect(ReconnectPolicy {
+                initial_delay: Duration::from_millis(10),
+                ..Default::default()
+            })
+            .connect()
+            .await
+            .unwrap();
+
+        let mut notifications = client.notifications();
+
+        client
+            .subscribe(Channel::Level2, ["BTC-USD"])
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            notifications.next().await,
+            Some(Ok(Notification::Disconnected(..)))
+        ));
+        assert_eq!(
+            notifications.next().await.unwrap().unwrap(),
+            Notification::Resync(Resync::Reconnected)
+        );
+
+        let requests = server.await.unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0], requests[1]);
+        assert_eq!(
+            requests[1],
+            WsMessage::text(r#"{"type":"subscribe","product_ids":["BTC-USD"],"channel":"level2"}"#)
+        );
+
+        assert!(
+            client
+                .subscribe(Channel::User, Vec::<String>::new())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_give_up_ends_streams() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("ws://{}", listener.local_addr().unwrap())).unwrap();
+
+        // Accept one connection, then stop listening: the reconnections are refused
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+            socket.next().await.unwrap().unwrap();
+        });
+
+        let client = WebSocketClient::builder()
+            .endpoint(Endpoint::Custom(url))
+            .reconnect(ReconnectPolicy {
+                initial_delay: Duration::from_millis(10),
+                max_attempts: Some(2),
+                ..Default::default()
+            })
+            .connect()
+            .await
+            .unwrap();
+
+        let notifications = client.notifications();
+        let product_stream = client.product_stream("BTC-USD");
+
+        client
+            .subscribe(Channel::Level2, ["BTC-USD"])
+            .await
+            .unwrap();
+        server.await.unwrap();
+
+        let notifications: Vec<Notification> =
+            tokio::time::timeout(Duration::from_secs(5), notifications.collect::<Vec<_>>())
+                .await
+                .expect("stream not ended")
+                .into_iter()
+                .map(Result::unwrap)
+                .collect();
+        assert!(matches!(notifications[0], Notification::Disconnected(..)));
+        assert_eq!(
+            notifications.last().unwrap(),
+            &Notification::Error(String::from("giving up reconnecting after 2 attempts"))
+        );
+
+        let remaining =
+            tokio::time::timeout(Duration::from_secs(5), product_stream.collect::<Vec<_>>()).await;
+        assert!(remaining.is_ok(), "product stream not ended");
+    }
+}
@@ -0,0 +1,313 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use serde::Serialize;
+use tokio::net::TcpStream;
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use url::Url;
+
+use super::channel::Channel;
+use super::client::Subscriptions;
+use super::error::Error;
+use super::message::{self, Notification, Resync};
+use super::reconnect::ReconnectPolicy;
+use crate::app::auth::jwt::JwtGenerator;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+pub(super) enum Command {
+    Subscribe {
+        channel: Channel,
+        product_ids: Vec<String>,
+        reply: oneshot::Sender<Result<(), Error>>,
+    },
+    Unsubscribe {
+        channel: Channel,
+        product_ids: Vec<String>,
+        reply: oneshot::Sender<Result<(), Error>>,
+    },
+}
+
+#[derive(Serialize)]
+struct SubscriptionRequest<'a> {
+    r#type: &'a str,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    product_ids: &'a [String],
+    channel: Channel,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    jwt: Option<String>,
+}
+
+/// Why a session ended.
+enum Exit {
+    /// The client has been dropped.
+    Shutdown,
+    /// The connection has been lost.
+    Disconnected(String),
+}
+
+/// Task that owns the socket and keeps it alive.
+pub(super) struct Connection {
+    pub(super) url: Url,
+    /// JWT generator, used to sign the subscriptions.
+    pub(super) jwt: Option<JwtGenerator>,
+    pub(super) reconnect: ReconnectPolicy,
+    pub(super) commands: mpsc::UnboundedReceiver<Command>,
+    pub(super) notifications: broadcast::Sender<Notification>,
+    /// Active subscriptions, shared with the client.
+    pub(super) subscriptions: Arc<Mutex<Subscriptions>>,
+    /// Dropped with the task, to end the notification streams.
+    pub(super) _closed: watch::Sender<()>,
+}
+
+impl Connection {
+    pub(super) async fn connect(url: &Url) -> Result<Socket, Error> {
+        let (socket, _) = tokio_tungstenite::connect_async(url.as_str()).await?;
+        Ok(socket)
+    }
+
+    pub(super) async fn run(mut self, mut socket: Socket) {
+        loop {
+            let reason: String = match self.session(&mut socket).await {
+                Exit::Shutdown => {
+                    let _ = socket.close(None).await;
+                    return;
+                }
+                Exit::Disconnected(reason) => reason,
+            };
+
+            self.notify(Notification::Disconnected(reason));
+
+            socket = match self.reconnect().await {
+                Some(socket) => socket,
+                None => return,
+            };
+
+            self.notify(Notification::Resync(Resync::Reconnected));
+        }
+    }
+
+    /// Handles commands and incoming messages until the connection drops.
+    async fn session(&mut self, socket: &mut Socket) -> Exit {
+        // Sequence numbers restart from zero on every connection
+        let mut last_sequence_num: Option<u64> = None;
+
+        loop {
+            tokio::select! {
+                command = self.commands.recv() => match command {
+                    Some(command) => self.handle_command(Some(socket), command).await,
+                    None => return Exit::Shutdown,
+                },
+                msg = socket.next() => match msg {
+                    Some(Ok(WsMessage::Text(text))) => {
+                        let notification = message::decode(text.as_str())
+                            .unwrap_or_else(|e| Notification::Error(e.to_string()));
+
+                        if let Notification::Message(message) = &notification {
+                            if let Some(last) = last_sequence_num {
+                                if message.sequence_num > last + 1 {
+                                    self.notify(Notification::Resync(Resync::SequenceGap {
+                                        expected: last + 1,
+                                        received: message.sequence_num,
+                                    }));
+                                }
+                            }
+
+                            last_sequence_num = Some(message.sequence_num);
+                        }
+
+                        self.notify(notification);
+                    }
+                    Some(Ok(WsMessage::Close(frame))) => {
+                        return Exit::Disconnected(frame.map(|f| f.reason.to_string()).unwrap_or_default());
+                    }
+                    Some(Ok(..)) => {}
+                    Some(Err(e)) => return Exit::Disconnected(e.to_string()),
+                    None => return Exit::Disconnected(String::from("connection closed")),
+                }
+            }
+        }
+    }
+
+    /// Reconnects with exponential backoff, and restores the subscriptions.
+    ///
+    /// An attempt fails if any subscription can't be restored.
+    /// Returns `None` if the client has been dropped or the policy gave up.
+    async fn reconnect(&mut self) -> Option<Socket> {
+        let mut attempt: u32 = 0;
+
+        loop {
+            if self
+                .reconnect
+                .max_attempts
+                .is_some_and(|max| attempt >= max)
+            {
+                self.notify(Notification::Error(format!(
+                    "giving up reconnecting after {attempt} attempts"
+                )));
+                return None;
+            }
+
+            let delay: Duration = self.reconnect.delay(attempt);
+            attempt += 1;
+
+            // Keep accepting (un)subscriptions while waiting: they will be applied on reconnect.
+            let sleep = tokio::time::sleep(delay);
+            tokio::pin!(sleep);
+
+            loop {
+                tokio::select! {
+                    _ = &mut sleep => break,
+                    command = self.commands.recv() => match command {
+                        Some(command) => self.handle_command(None, command).await,
+                        None => return None,
+                    },
+                }
+            }
+
+            let mut socket: Socket = match Self::connect(&self.url).await {
+                Ok(socket) => socket,
+                Err(e) => {
+                    self.notify(Notification::Error(e.to_string()));
+                    continue;
+                }
+            };
+
+            match self.resubscribe(&mut socket).await {
+                Ok(()) => return Some(socket),
+                Err(e) => {
+                    self.notify(Notification::Error(e.to_string()));
+                    let _ = socket.close(None).await;
+                }
+            }
+        }
+    }
+
+    async fn resubscribe(&self, socket: &mut Socket) -> Result<(), Error> {
+        let subscriptions: Subscriptions = self.subscriptions();
+
+        for (channel, product_ids) in subscriptions.into_iter() {
+            let product_ids: Vec<String> = product_ids.into_iter().collect();
+            self.send_request(socket, "subscribe", channel, &product_ids)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Applies a command.
+    ///
+    /// If the socket is `None` (disconnected), the command only updates the subscriptions.
+    async fn handle_command(&self, socket: Option<&mut Socket>, command: Command) {
+        match command {
+            Command::Subscribe {
+                channel,
+                product_ids,
+                reply,
+            } => {
+                let res = match socket {
+                    Some(socket) => {
+                        self.send_request(socket, "subscribe", channel, &product_ids)
+                            .await
+                    }
+                    None => Ok(()),
+                };
+
+                if res.is_ok() {
+                    let mut subscriptions = self.lock_subscriptions();
+                    subscriptions
+                        .entry(channel)
+                        .or_default()
+                        .extend(product_ids);
+                }
+
+                let _ = reply.send(res);
+            }
+            Command::Unsubscribe {
+                channel,
+                mut product_ids,
+                reply,
+            } => {
+                // An empty list means all the products of the channel
+                if product_ids.is_empty() {
+                    product_ids = self
+                        .subscriptions()
+                        .remove(&channel)
+                        .map(|ids| ids.into_iter().collect())
+                        .unwrap_or_default();
+                }
+
+                let res = match socket {
+                    Some(socket) => {
+                        self.send_request(socket, "unsubscribe", channel, &product_ids)
+                            .await
+                    }
+                    None => Ok(()),
+                };
+
+                if res.is_ok() {
+                    let mut subscriptions = self.lock_subscriptions();
+                    if let Some(ids) = subscriptions.get_mut(&channel) {
+                        for id in product_ids.iter() {
+                            ids.remove(id);
+                        }
+
+                        if ids.is_empty() {
+                            subscriptions.remove(&channel);
+                        }
+                    }
+                }
+
+                let _ = reply.send(res);
+            }
+        }
+    }
+
+    async fn send_request(
+        &self,
+        socket: &mut Socket,
+        r#type: &str,
+        channel: Channel,
+        product_ids: &[String],
+    ) -> Result<(), Error> {
+        // A fresh JWT for every message, since they expire after 2 minutes.
+        // This also covers the re-subscriptions after a reconnection.
+        let jwt: Option<String> = match &self.jwt {
+            Some(jwt) => Some(jwt.encode(None).await?),
+            None => None,
+        };
+
+        let request = SubscriptionRequest {
+            r#type,
+            product_ids,
+            channel,
+            jwt,
+        };
+        let request: String = serde_json::to_string(&request)?;
+
+        socket.send(WsMessage::text(request)).await?;
+
+        Ok(())
+    }
+
+    #[inline]
+    fn notify(&self, notification: Notification) {
+        // No receivers is not an error
+        let _ = self.notifications.send(notification);
+    }
+
+    #[inline]
+    fn subscriptions(&self) -> Subscriptions {
+        self.lock_subscriptions().clone()
+    }
+
+    #[inline]
+    fn lock_subscriptions(&self) -> std::sync::MutexGuard<'_, Subscriptions> {
+        self.subscriptions
+            .lock()
+            .expect("subscriptions lock poisoned")
+    }
+}
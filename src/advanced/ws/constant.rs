@@ -0,0 +1,8 @@
+/// Market data WebSocket endpoint
+pub(super) const WS_MARKET_DATA_URL: &str = "wss://advanced-trade-ws.coinbase.com";
+
+/// User order data WebSocket endpoint
+pub(super) const WS_USER_DATA_URL: &str = "wss://advanced-trade-ws-user.coinbase.com";
+
+/// Default capacity of the notifications channel
+pub(super) const DEFAULT_CAPACITY: usize = 1024;
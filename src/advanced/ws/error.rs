@@ -0,0 +1,41 @@
+//! Advanced Trade WebSocket error
+
+use thiserror::Error;
+use tokio_tungstenite::tungstenite;
+
+use super::channel::Channel;
+
+/// Advanced Trade WebSocket error
+#[derive(Debug, Error)]
+pub enum Error {
+    /// WebSocket error
+    #[error(transparent)]
+    WebSocket(Box<tungstenite::Error>),
+    /// Url error
+    #[error(transparent)]
+    Url(#[from] url::ParseError),
+    /// JSON error
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    /// Coinbase App error
+    #[error(transparent)]
+    App(#[from] crate::app::error::Error),
+    /// The WebSocket only supports the API keys authentication
+    #[error("unsupported authentication")]
+    UnsupportedAuthentication,
+    /// The channel requires authentication
+    #[error("authentication required for the {0} channel")]
+    AuthenticationRequired(Channel),
+    /// The connection has been closed
+    #[error("connection closed")]
+    Closed,
+    /// The stream fell behind and skipped some notifications
+    #[error("lagged behind by {0} notifications")]
+    Lagged(u64),
+}
+
+impl From<tungstenite::Error> for Error {
+    fn from(e: tungstenite::Error) -> Self {
+        Self::WebSocket(Box::new(e))
+    }
+}
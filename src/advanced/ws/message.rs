@@ -0,0 +1,748 @@
+//! Advanced Trade WebSocket messages
+//!
+//! <https://docs.cdp.coinbase.com/advanced-trade/docs/ws-channels>
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::channel::Channel;
+use super::error::Error;
+use crate::util::de::{
+    deserialize_optional_string_to_f64, deserialize_string_to_f64, deserialize_string_to_u64,
+};
+
+/// Notification received from the WebSocket feed
+#[derive(Debug, Clone, PartialEq)]
+pub enum Notification {
+    /// Channel message
+    Message(Message),
+    /// Error reported by Coinbase or message that couldn't be decoded
+    Error(String),
+    /// The connection has been lost
+    Disconnected(String),
+    /// Some notifications may have been missed
+    Resync(Resync),
+}
+
+/// Signal that some notifications may have been missed.
+///
+/// Consumers should reconcile their state out-of-band (i.e., listing the orders via the REST APIs).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Resync {
+    /// The connection has been re-established and the subscriptions restored
+    Reconnected,
+    /// A gap in the sequence numbers has been detected
+    SequenceGap {
+        /// Expected sequence number
+        expected: u64,
+        /// Received sequence number
+        received: u64,
+    },
+}
+
+/// Channel message
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    /// Client ID
+    pub client_id: String,
+    /// Timestamp
+    pub timestamp: DateTime<Utc>,
+    /// Sequence number, shared by all the channels of the connection
+    pub sequence_num: u64,
+    /// Events
+    pub events: Vec<Event>,
+}
+
+impl Message {
+    /// Keep only the events related to the provided product.
+    ///
+    /// Returns `false` if no event is left.
+    pub fn retain_product(&mut self, product_id: &str) -> bool {
+        self.events
+            .retain_mut(|event| event.retain_product(product_id));
+        !self.events.is_empty()
+    }
+}
+
+/// Event type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+pub enum EventType {
+    /// Full state, sent after the subscription
+    #[serde(rename = "snapshot")]
+    Snapshot,
+    /// Incremental update
+    #[serde(rename = "update")]
+    Update,
+}
+
+/// Channel event
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// Ticker event
+    Ticker(TickerEvent),
+    /// Ticker batch event
+    TickerBatch(TickerEvent),
+    /// Level 2 event
+    Level2(Level2Event),
+    /// Market trades event
+    MarketTrades(MarketTradesEvent),
+    /// Candles event
+    Candles(CandlesEvent),
+    /// Status event
+    Status(StatusEvent),
+    /// Heartbeats event
+    Heartbeats(HeartbeatsEvent),
+    /// User event
+    User(UserEvent),
+    /// Futures balance summary event
+    FuturesBalanceSummary(FuturesBalanceSummaryEvent),
+    /// Active subscriptions
+    Subscriptions(SubscriptionsEvent),
+}
+
+impl Event {
+    /// Channel that produced the event
+    ///
+    /// Returns `None` for the subscriptions acknowledgements.
+    pub fn channel(&self) -> Option<Channel> {
+        match self {
+            Self::Ticker(..) => Some(Channel::Ticker),
+            Self::TickerBatch(..) => Some(Channel::TickerBatch),
+            Self::Level2(..) => Some(Channel::Level2),
+            Self::MarketTrades(..) => Some(Channel::MarketTrades),
+            Self::Candles(..) => Some(Channel::Candles),
+            Self::Status(..) => Some(Channel::Status),
+            Self::Heartbeats(..) => Some(Channel::Heartbeats),
+            Self::User(..) => Some(Channel::User),
+            Self::FuturesBalanceSummary(..) => Some(Channel::FuturesBalanceSummary),
+            Self::Subscriptions(..) => None,
+        }
+    }
+
+    /// Keep only the data related to the provided product.
+    ///
+    /// Returns `false` if the event doesn't concern the product.
+    /// The events that don't belong to any product (i.e., heartbeats) are kept.
+    pub fn retain_product(&mut self, product_id: &str) -> bool {
+        match self {
+            Self::Ticker(event) | Self::TickerBatch(event) => {
+                event.tickers.retain(|t| t.product_id == product_id);
+                !event.tickers.is_empty()
+            }
+            Self::Level2(event) => event.product_id == product_id,
+            Self::MarketTrades(event) => {
+                event.trades.retain(|t| t.product_id == product_id);
+                !event.trades.is_empty()
+            }
+            Self::Candles(event) => {
+                event.candles.retain(|c| c.product_id == product_id);
+                !event.candles.is_empty()
+            }
+            Self::Status(event) => {
+                event.products.retain(|p| p.id == product_id);
+                !event.products.is_empty()
+            }
+            Self::User(event) => {
+                event.orders.retain(|o| o.product_id == product_id);
+                !event.orders.is_empty()
+            }
+            Self::Heartbeats(..) | Self::FuturesBalanceSummary(..) | Self::Subscriptions(..) => {
+                true
+            }
+        }
+    }
+}
+
+/// Ticker event
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TickerEvent {
+    /// Event type
+    pub r#type: EventType,
+    /// Tickers
+    pub tickers: Vec<Ticker>,
+}
+
+/// Ticker
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Ticker {
+    /// Product ID (i.e., BTC-USD)
+    pub product_id: String,
+    /// Last price
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub price: f64,
+    /// 24h volume
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub volume_24_h: f64,
+    /// 24h low
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub low_24_h: f64,
+    /// 24h high
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub high_24_h: f64,
+    /// 52w low
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub low_52_w: f64,
+    /// 52w high
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub high_52_w: f64,
+    /// 24h price change, in percentage
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub price_percent_chg_24_h: f64,
+    /// Best bid
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub best_bid: f64,
+    /// Best bid quantity
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub best_bid_quantity: f64,
+    /// Best ask
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub best_ask: f64,
+    /// Best ask quantity
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub best_ask_quantity: f64,
+}
+
+/// Level 2 event
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Level2Event {
+    /// Event type
+    pub r#type: EventType,
+    /// Product ID (i.e., BTC-USD)
+    pub product_id: String,
+    /// Price level updates
+    pub updates: Vec<Level2Update>,
+}
+
+/// Order book side
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+pub enum Side {
+    /// Bid
+    #[serde(rename = "bid")]
+    Bid,
+    /// Offer (ask)
+    #[serde(rename = "offer")]
+    Offer,
+}
+
+/// Level 2 price level update
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Level2Update {
+    /// Side
+    pub side: Side,
+    /// Event time
+    pub event_time: DateTime<Utc>,
+    /// Price level
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub price_level: f64,
+    /// New quantity at the price level.
+    ///
+    /// Zero means that the price level has been removed.
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub new_quantity: f64,
+}
+
+/// Market trades event
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MarketTradesEvent {
+    /// Event type
+    pub r#type: EventType,
+    /// Trades
+    pub trades: Vec<MarketTrade>,
+}
+
+/// Trade side
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+pub enum TradeSide {
+    /// Buy
+    #[serde(rename = "BUY")]
+    Buy,
+    /// Sell
+    #[serde(rename = "SELL")]
+    Sell,
+}
+
+/// Market trade
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MarketTrade {
+    /// Trade ID
+    pub trade_id: String,
+    /// Product ID (i.e., BTC-USD)
+    pub product_id: String,
+    /// Price
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub price: f64,
+    /// Size
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub size: f64,
+    /// Maker side
+    pub side: TradeSide,
+    /// Time
+    pub time: DateTime<Utc>,
+}
+
+/// Candles event
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct CandlesEvent {
+    /// Event type
+    pub r#type: EventType,
+    /// Candles
+    pub candles: Vec<Candle>,
+}
+
+/// Candle (5 minutes granularity)
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Candle {
+    /// Product ID (i.e., BTC-USD)
+    pub product_id: String,
+    /// Start of the bucket, UNIX timestamp
+    #[serde(deserialize_with = "deserialize_string_to_u64")]
+    pub start: u64,
+    /// Open price
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub open: f64,
+    /// High price
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub high: f64,
+    /// Low price
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub low: f64,
+    /// Close price
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub close: f64,
+    /// Volume
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub volume: f64,
+}
+
+/// Status event
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct StatusEvent {
+    /// Event type
+    pub r#type: EventType,
+    /// Products
+    pub products: Vec<ProductStatus>,
+}
+
+/// Product status
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ProductStatus {
+    /// Product ID (i.e., BTC-USD)
+    pub id: String,
+    /// Product type (i.e., SPOT)
+    pub product_type: String,
+    /// Base currency
+    pub base_currency: String,
+    /// Quote currency
+    pub quote_currency: String,
+    /// Base increment
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub base_increment: f64,
+    /// Quote increment
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub quote_increment: f64,
+    /// Display name
+    pub display_name: String,
+    /// Status (i.e., online)
+    pub status: String,
+    /// Status message
+    pub status_message: String,
+    /// Minimum market funds
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub min_market_funds: f64,
+}
+
+/// Heartbeats event
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct HeartbeatsEvent {
+    /// Server time
+    pub current_time: String,
+    /// Heartbeat counter
+    #[serde(deserialize_with = "deserialize_string_to_u64")]
+    pub heartbeat_counter: u64,
+}
+
+/// User event
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct UserEvent {
+    /// Event type
+    pub r#type: EventType,
+    /// Orders
+    pub orders: Vec<UserOrder>,
+}
+
+/// Order of the user
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct UserOrder {
+    /// Order ID
+    pub order_id: String,
+    /// Client order ID
+    pub client_order_id: String,
+    /// Product ID (i.e., BTC-USD)
+    pub product_id: String,
+    /// Product type (i.e., SPOT)
+    pub product_type: String,
+    /// Order side
+    pub order_side: TradeSide,
+    /// Order type (i.e., Limit)
+    pub order_type: String,
+    /// Order status (i.e., OPEN, FILLED, CANCELLED)
+    pub status: String,
+    /// Time in force (i.e., GOOD_UNTIL_CANCELLED)
+    pub time_in_force: String,
+    /// Average filled price
+    #[serde(default, deserialize_with = "deserialize_optional_string_to_f64")]
+    pub avg_price: Option<f64>,
+    /// Limit price
+    #[serde(default, deserialize_with = "deserialize_optional_string_to_f64")]
+    pub limit_price: Option<f64>,
+    /// Stop price
+    #[serde(default, deserialize_with = "deserialize_optional_string_to_f64")]
+    pub stop_price: Option<f64>,
+    /// Filled quantity
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub cumulative_quantity: f64,
+    /// Quantity left to fill
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub leaves_quantity: f64,
+    /// Filled value
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub filled_value: f64,
+    /// Number of fills
+    #[serde(deserialize_with = "deserialize_string_to_u64")]
+    pub number_of_fills: u64,
+    /// Total fees
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub total_fees: f64,
+    /// Cancel reason
+    #[serde(default)]
+    pub cancel_reason: String,
+    /// Reject reason
+    #[serde(default, alias = "reject_Reason")]
+    pub reject_reason: String,
+    /// Creation time
+    pub creation_time: DateTime<Utc>,
+}
+
+/// Futures balance summary event
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct FuturesBalanceSummaryEvent {
+    /// Event type
+    pub r#type: EventType,
+    /// Balance summary
+    pub fcm_balance_summary: FuturesBalanceSummary,
+}
+
+/// Futures balance summary
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct FuturesBalanceSummary {
+    /// Futures buying power
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub futures_buying_power: f64,
+    /// Total USD balance
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub total_usd_balance: f64,
+    /// USD balance in the spot account
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub cbi_usd_balance: f64,
+    /// USD balance in the futures account
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub cfm_usd_balance: f64,
+    /// Amount on hold for the open orders
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub total_open_orders_hold_amount: f64,
+    /// Unrealized PnL
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub unrealized_pnl: f64,
+    /// Daily realized PnL
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub daily_realized_pnl: f64,
+    /// Initial margin
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub initial_margin: f64,
+    /// Available margin
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub available_margin: f64,
+    /// Liquidation threshold
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub liquidation_threshold: f64,
+    /// Liquidation buffer amount
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub liquidation_buffer_amount: f64,
+    /// Liquidation buffer percentage
+    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    pub liquidation_buffer_percentage: f64,
+}
+
+/// Active subscriptions
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SubscriptionsEvent {
+    /// Product IDs by channel
+    pub subscriptions: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct RawMessage {
+    channel: String,
+    #[serde(default)]
+    client_id: String,
+    timestamp: DateTime<Utc>,
+    sequence_num: u64,
+    events: Value,
+}
+
+#[derive(Deserialize)]
+struct ErrorMessage {
+    message: String,
+}
+
+/// Decodes a text frame received from the WebSocket.
+pub(super) fn decode(text: &str) -> Result<Notification, Error> {
+    let value: Value = serde_json::from_str(text)?;
+
+    // Errors don't have a channel
+    if value.get("type").and_then(Value::as_str) == Some("error") {
+        let error: ErrorMessage = serde_json::from_value(value)?;
+        return Ok(Notification::Error(error.message));
+    }
+
+    let raw: RawMessage = serde_json::from_value(value)?;
+
+    let events: Vec<Event> = match raw.channel.as_str() {
+        "ticker" => decode_events(raw.events, Event::Ticker)?,
+        "ticker_batch" => decode_events(raw.events, Event::TickerBatch)?,
+        "l2_data" => decode_events(raw.events, Event::Level2)?,
+        "market_trades" => decode_events(raw.events, Event::MarketTrades)?,
+        "candles" => decode_events(raw.events, Event::Candles)?,
+        "status" => decode_events(raw.events, Event::Status)?,
+        "heartbeats" => decode_events(raw.events, Event::Heartbeats)?,
+        "user" => decode_events(raw.events, Event::User)?,
+        "futures_balance_summary" => decode_events(raw.events, Event::FuturesBalanceSummary)?,
+        "subscriptions" => decode_events(raw.events, Event::Subscriptions)?,
+        channel => return Ok(Notification::Error(format!("unknown channel: {channel}"))),
+    };
+
+    Ok(Notification::Message(Message {
+        client_id: raw.client_id,
+        timestamp: raw.timestamp,
+        sequence_num: raw.sequence_num,
+        events,
+    }))
+}
+
+fn decode_events<T, F>(events: Value, f: F) -> Result<Vec<Event>, Error>
+where
+    T: for<'de> Deserialize<'de>,
+    F: Fn(T) -> Event,
+{
+    let events: Vec<T> = serde_json::from_value(events)?;
+    Ok(events.into_iter().map(f).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_message(json: &str) -> Message {
+        match decode(json).unwrap() {
+            Notification::Message(message) => message,
+            notification => panic!("unexpected notification: {notification:?}"),
+        }
+    }
+
+    #[test]
+    fn test_decode_ticker() {
+        let json = r#"
+        {
+          "channel": "ticker",
+          "client_id": "",
+          "timestamp": "2023-02-09T20:30:37.167359596Z",
+          "sequence_num": 0,
+          "events": [
+            {
+              "type": "snapshot",
+              "tickers": [
+                {
+                  "type": "ticker",
+                  "product_id": "BTC-USD",
+                  "price": "21932.98",
+                  "volume_24_h": "16038.28770938",
+                  "low_24_h": "21835.29",
+                  "high_24_h": "23011.18",
+                  "low_52_w": "15460",
+                  "high_52_w": "48240",
+                  "price_percent_chg_24_h": "-4.15775596190603",
+                  "best_bid": "21931.98",
+                  "best_bid_quantity": "8000.21",
+                  "best_ask": "21933.98",
+                  "best_ask_quantity": "8038.07770938"
+                }
+              ]
+            }
+          ]
+        }"#;
+
+        let mut message = decode_message(json);
+        assert_eq!(message.sequence_num, 0);
+        assert_eq!(message.events.len(), 1);
+
+        match &message.events[0] {
+            Event::Ticker(event) => {
+                assert_eq!(event.r#type, EventType::Snapshot);
+                assert_eq!(event.tickers[0].product_id, "BTC-USD");
+                assert_eq!(event.tickers[0].price, 21932.98);
+                assert_eq!(event.tickers[0].best_ask, 21933.98);
+            }
+            event => panic!("unexpected event: {event:?}"),
+        }
+
+        assert!(message.retain_product("BTC-USD"));
+        assert!(!message.retain_product("ETH-USD"));
+    }
+
+    #[test]
+    fn test_decode_level2() {
+        let json = r#"
+        {
+          "channel": "l2_data",
+          "client_id": "",
+          "timestamp": "2023-02-09T20:32:50.714964855Z",
+          "sequence_num": 3,
+          "events": [
+            {
+              "type": "update",
+              "product_id": "BTC-USD",
+              "updates": [
+                {
+                  "side": "bid",
+                  "event_time": "1970-01-01T00:00:00Z",
+                  "price_level": "21921.73",
+                  "new_quantity": "0.06317902"
+                },
+                {
+                  "side": "offer",
+                  "event_time": "1970-01-01T00:00:00Z",
+                  "price_level": "21921.3",
+                  "new_quantity": "0"
+                }
+              ]
+            }
+          ]
+        }"#;
+
+        let message = decode_message(json);
+        assert_eq!(message.sequence_num, 3);
+
+        match &message.events[0] {
+            Event::Level2(event) => {
+                assert_eq!(event.r#type, EventType::Update);
+                assert_eq!(event.product_id, "BTC-USD");
+                assert_eq!(event.updates.len(), 2);
+                assert_eq!(event.updates[0].side, Side::Bid);
+                assert_eq!(event.updates[1].side, Side::Offer);
+                assert_eq!(event.updates[1].new_quantity, 0.0);
+            }
+            event => panic!("unexpected event: {event:?}"),
+        }
+    }
+
+    #[test]
+    fn test_decode_heartbeats_and_errors() {
+        let json = r#"
+        {
+          "channel": "heartbeats",
+          "client_id": "",
+          "timestamp": "2023-06-23T20:31:56.121961769Z",
+          "sequence_num": 0,
+          "events": [
+            {
+              "current_time": "2023-06-23 20:31:56.121961769 +0000 UTC m=+91717.525857105",
+              "heartbeat_counter": "3049"
+            }
+          ]
+        }"#;
+
+        let mut message = decode_message(json);
+        match &message.events[0] {
+            Event::Heartbeats(event) => assert_eq!(event.heartbeat_counter, 3049),
+            event => panic!("unexpected event: {event:?}"),
+        }
+        assert!(message.retain_product("BTC-USD"));
+
+        let json = r#"{"type":"error","message":"failure to subscribe"}"#;
+        assert_eq!(
+            decode(json).unwrap(),
+            Notification::Error(String::from("failure to subscribe"))
+        );
+    }
+
+    #[test]
+    fn test_decode_user() {
+        let json = r#"
+        {
+          "channel": "user",
+          "client_id": "",
+          "timestamp": "2023-02-09T20:33:57.609931463Z",
+          "sequence_num": 0,
+          "events": [
+            {
+              "type": "snapshot",
+              "orders": [
+                {
+                  "avg_price": "50000",
+                  "cancel_reason": "",
+                  "client_order_id": "XXX",
+                  "completion_percentage": "100.00",
+                  "contract_expiry_type": "UNKNOWN_CONTRACT_EXPIRY_TYPE",
+                  "cumulative_quantity": "0.01",
+                  "filled_value": "500",
+                  "leaves_quantity": "0",
+                  "limit_price": "",
+                  "number_of_fills": "2",
+                  "order_id": "YYY",
+                  "order_side": "BUY",
+                  "order_type": "Market",
+                  "outstanding_hold_amount": "0",
+                  "post_only": "false",
+                  "product_id": "BTC-USD",
+                  "product_type": "SPOT",
+                  "reject_Reason": "",
+                  "retail_portfolio_id": "ZZZ",
+                  "risk_managed_by": "UNKNOWN_RISK_MANAGEMENT_TYPE",
+                  "status": "FILLED",
+                  "stop_price": "",
+                  "time_in_force": "IMMEDIATE_OR_CANCEL",
+                  "total_fees": "3",
+                  "total_value_after_fees": "503",
+                  "trigger_status": "INVALID_ORDER_TYPE",
+                  "creation_time": "2023-02-09T20:33:57.609931463Z",
+                  "end_time": "0001-01-01T00:00:00Z",
+                  "start_time": "0001-01-01T00:00:00Z"
+                }
+              ],
+              "positions": {
+                "perpetual_futures_positions": [],
+                "expiring_futures_positions": []
+              }
+            }
+          ]
+        }"#;
+
+        let message = decode_message(json);
+        match &message.events[0] {
+            Event::User(event) => {
+                let order = &event.orders[0];
+                assert_eq!(order.order_id, "YYY");
+                assert_eq!(order.order_side, TradeSide::Buy);
+                assert_eq!(order.status, "FILLED");
+                assert_eq!(order.avg_price, Some(50000.0));
+                assert_eq!(order.limit_price, None);
+                assert_eq!(order.cumulative_quantity, 0.01);
+                assert_eq!(order.number_of_fills, 2);
+            }
+            event => panic!("unexpected event: {event:?}"),
+        }
+    }
+}
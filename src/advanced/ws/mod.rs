@@ -0,0 +1,13 @@
+//! Coinbase Advanced Trade WebSocket APIs
+//!
+//! <https://docs.cdp.coinbase.com/advanced-trade/docs/ws-overview>
+
+pub mod book;
+pub mod builder;
+pub mod channel;
+pub mod client;
+mod connection;
+mod constant;
+pub mod error;
+pub mod message;
+pub mod reconnect;
@@ -0,0 +1,91 @@
+//! Advanced Trade WebSocket reconnection policy
+
+use std::time::Duration;
+
+/// Reconnection policy, with exponential backoff
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReconnectPolicy {
+    /// Delay before the first attempt
+    pub initial_delay: Duration,
+    /// Maximum delay between two attempts
+    pub max_delay: Duration,
+    /// Factor applied to the delay after every failed attempt
+    ///
+    /// A negative factor is handled as `0.0`, and NaN as `1.0` (constant delay).
+    pub multiplier: f64,
+    /// Maximum number of consecutive attempts (`None` means unlimited)
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            multiplier: 2.0,
+            max_attempts: None,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Never reconnect
+    #[inline]
+    pub fn disabled() -> Self {
+        Self {
+            max_attempts: Some(0),
+            ..Default::default()
+        }
+    }
+
+    /// Delay to wait before the provided attempt (starting from `0`)
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let multiplier: f64 = if self.multiplier.is_nan() {
+            1.0
+        } else {
+            self.multiplier.max(0.0)
+        };
+        let factor: f64 = multiplier.powi(attempt.min(i32::MAX as u32) as i32);
+        // `0 * inf` is NaN: `max` turns it into `0`
+        let delay: f64 = (self.initial_delay.as_secs_f64() * factor).max(0.0);
+        Duration::from_secs_f64(delay.min(self.max_delay.as_secs_f64()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exponential_backoff() {
+        let policy = ReconnectPolicy::default();
+        assert_eq!(policy.delay(0), Duration::from_secs(1));
+        assert_eq!(policy.delay(1), Duration::from_secs(2));
+        assert_eq!(policy.delay(3), Duration::from_secs(8));
+        assert_eq!(policy.delay(10), Duration::from_secs(60));
+        assert_eq!(policy.delay(u32::MAX), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_invalid_multiplier() {
+        let policy = ReconnectPolicy {
+            multiplier: -2.0,
+            ..Default::default()
+        };
+        assert_eq!(policy.delay(0), Duration::from_secs(1));
+        assert_eq!(policy.delay(1), Duration::ZERO);
+
+        let policy = ReconnectPolicy {
+            multiplier: f64::NAN,
+            ..Default::default()
+        };
+        assert_eq!(policy.delay(5), Duration::from_secs(1));
+
+        let policy = ReconnectPolicy {
+            initial_delay: Duration::ZERO,
+            multiplier: f64::INFINITY,
+            ..Default::default()
+        };
+        assert_eq!(policy.delay(1), Duration::ZERO);
+    }
+}
@@ -1,101 +1,398 @@
-use std::time::Duration;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use reqwest::header::{CONTENT_TYPE, HeaderValue, USER_AGENT};
-use reqwest::{Client, Method, Response};
+use http::header::{AUTHORIZATION, CONTENT_TYPE, HeaderValue, USER_AGENT};
+use http::{Method, StatusCode};
+use serde::de::DeserializeOwned;
 use url::Url;
+use zeroize::Zeroizing;
 
 use super::auth::CoinbaseAuth;
-use super::auth::jwt::Jwt;
+use super::auth::cache::{JwtCache, JwtCacheStats};
+use super::auth::jwt::JwtGenerator;
+use super::auth::oauth2::OAuth2Session;
+use super::auth::secret::SecretString;
+use super::builder::CoinbaseAppClientBuilder;
+use super::cassette::{Cassette, CassetteMode, RecordedRequest};
 use super::constant::{API_ROOT_URL, API_SANDBOX_URL, CB_VERSION, USER_AGENT_NAME};
-use super::error::Error;
+use super::error::{Error, ErrorContext};
+use super::metrics::{MetricsRecorder, StatusClass, endpoint_template};
+use super::middleware::{Middleware, RequestContext};
+use super::response::CoinbaseErrorResponse;
+use super::retry::RetryPolicy;
+#[cfg(feature = "reqwest")]
+use super::transport::ReqwestTransport;
+use super::transport::{BoxFuture, HttpRequest, HttpResponse, HttpTransport};
 
 #[derive(Debug, Clone)]
 struct HttpClientAgent {
     /// Root URL for the API.
     root_url: Url,
-    /// HTTP client.
-    client: Client,
+    /// HTTP transport.
+    transport: Arc<dyn HttpTransport>,
+    /// Cassette, to record or replay the requests.
+    cassette: Option<Arc<Cassette>>,
+    /// Middlewares, called around every request.
+    middlewares: Vec<Arc<dyn Middleware>>,
+    /// Retry policy, disabled by default.
+    retry: Option<RetryPolicy>,
+    /// Metrics recorder.
+    metrics: Option<Arc<dyn MetricsRecorder>>,
 }
 
 impl HttpClientAgent {
-    fn new(sandbox: bool, timeout: Duration) -> Result<Self, Error> {
-        let root_url: &str = if sandbox {
-            API_SANDBOX_URL
-        } else {
-            API_ROOT_URL
+    fn new(builder: &CoinbaseAppClientBuilder) -> Result<Self, Error> {
+        let mut root_url: Url = match &builder.base_url {
+            Some(base_url) => base_url.clone(),
+            None if builder.sandbox => Url::parse(API_SANDBOX_URL)?,
+            None => Url::parse(API_ROOT_URL)?,
         };
 
-        let client = Client::builder().timeout(timeout).build()?;
+        // Keep the path prefix (i.e., `https://proxy/coinbase`) when joining the resources
+        if !root_url.path().ends_with('/') {
+            let path: String = format!("{}/", root_url.path());
+            root_url.set_path(&path);
+        }
+
+        let transport: Arc<dyn HttpTransport> = match &builder.transport {
+            Some(transport) => transport.clone(),
+            #[cfg(feature = "reqwest")]
+            None => Arc::new(ReqwestTransport::new(builder.timeout)?),
+            #[cfg(not(feature = "reqwest"))]
+            None => {
+                return Err(Error::Transport(String::from(
+                    "no HTTP transport: enable the `reqwest` feature or provide one",
+                )));
+            }
+        };
 
         Ok(Self {
-            root_url: Url::parse(root_url)?,
-            client,
+            root_url,
+            transport,
+            cassette: builder.cassette.clone(),
+            middlewares: builder.middlewares.clone(),
+            retry: builder.retry,
+            metrics: builder.metrics.clone(),
         })
     }
 
     /// Constructs a URL for the request being made.
     fn build_url(&self, resource: &str, query: Option<&str>) -> Result<Url, Error> {
-        let mut url = self.root_url.join(resource)?;
-        url.set_query(query);
+        // Relative to the root URL, to keep its path prefix
+        let mut url = self.root_url.join(resource.trim_start_matches('/'))?;
+
+        // Keep the query of the resource (i.e., the pagination URIs)
+        if query.is_some() {
+            url.set_query(query);
+        }
+
         Ok(url)
     }
 
+    /// Path of the resource, without the path prefix of the root URL (i.e., `/v2/accounts`).
+    fn resource_path<'a>(&self, url: &'a Url) -> &'a str {
+        let prefix: &str = self.root_url.path().trim_end_matches('/');
+        url.path().strip_prefix(prefix).unwrap_or(url.path())
+    }
+
     /// Handles the response from the API.
-    async fn handle_response(&self, response: Response) -> Result<Response, Error> {
-        Ok(response.error_for_status()?)
+    fn handle_response(
+        &self,
+        context: &RequestContext,
+        response: HttpResponse,
+    ) -> Result<HttpResponse, Error> {
+        if response.status.is_success() {
+            return Ok(response);
+        }
 
-        // if response.status().is_success() {
-        //             Ok(response)
-        //         } else {
-        //             let res: CoinbaseErrorMessage = response.json().await?;
-        //             Err(Error::Coinbase(res))
-        //         }
+        // Error envelope
+        let error: Error = match response.json::<CoinbaseErrorResponse>() {
+            Ok(CoinbaseErrorResponse { errors }) if !errors.is_empty() => {
+                Error::Coinbase(errors.into_iter().next().expect("not empty"))
+            }
+            _ => Error::HttpStatus(response.status.as_u16()),
+        };
+
+        Err(error.with_context(ErrorContext::new(
+            context.method.clone(),
+            self.resource_path(&context.url),
+            Some(&response),
+        )))
     }
 
-    pub(crate) async fn execute_request(
+    /// Executes the request, with the token of `token_source`.
+    ///
+    /// The token is fetched again before every retry, since it may have expired while waiting.
+    pub(crate) async fn execute_request<F, Fut>(
         &self,
         method: Method,
         url: Url,
         body: Option<String>,
-        token: Option<String>,
-    ) -> Result<Response, Error> {
-        // {
-        //     let mut locked_bucket = self.bucket.lock().await;
-        //     locked_bucket.wait_on().await;
-        // }
+        token_source: F,
+    ) -> Result<HttpResponse, Error>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<Option<SecretString>, Error>>,
+    {
+        let mut request: HttpRequest = HttpRequest::new(method, url);
+        request
+            .headers
+            .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        request
+            .headers
+            .insert(USER_AGENT, HeaderValue::from_static(USER_AGENT_NAME));
+        request
+            .headers
+            .insert("CB-VERSION", HeaderValue::from_static(CB_VERSION));
 
-        let mut request = self
-            .client
-            .request(method, url)
-            .header(CONTENT_TYPE, "application/json")
-            .header(USER_AGENT, USER_AGENT_NAME)
-            .header("CB-VERSION", HeaderValue::from_static(CB_VERSION));
+        if let Some(token) = token_source().await? {
+            authorize(&mut request, &token)?;
+        }
+
+        request.body = body.map(String::into_bytes);
 
-        if let Some(token) = token {
-            request = request.bearer_auth(token);
+        for middleware in self.middlewares.iter() {
+            middleware.before_send(&mut request)?;
         }
 
-        if let Some(body) = body {
-            request = request.body(body);
+        #[cfg(feature = "tracing")]
+        {
+            use tracing::Instrument;
+            use tracing::field::Empty;
+
+            let span = tracing::info_span!(
+                "request",
+                method = %request.method,
+                path = self.resource_path(&request.url),
+                status = Empty,
+                latency_ms = Empty,
+                request_id = Empty,
+            );
+
+            return self
+                .execute(request, &token_source, |context, response| {
+                    self.handle_response(context, response)
+                })
+                .instrument(span)
+                .await;
         }
 
-        let response = request.send().await?;
+        #[cfg(not(feature = "tracing"))]
+        self.execute(request, &token_source, |context, response| {
+            self.handle_response(context, response)
+        })
+        .await
+    }
+
+    /// Sends a request built by the caller (i.e., the OAuth2 token requests), without token.
+    ///
+    /// The response is returned whatever its status, for the caller to handle.
+    async fn dispatch(&self, mut request: HttpRequest) -> Result<HttpResponse, Error> {
+        for middleware in self.middlewares.iter() {
+            middleware.before_send(&mut request)?;
+        }
 
-        self.handle_response(response).await
+        self.execute(request, &|| async { Ok(None) }, |_, response| Ok(response))
+            .await
+    }
+
+    /// Executes the request, retrying it according to the policy.
+    ///
+    /// The final response goes through `handle`.
+    async fn execute<F, Fut, H>(
+        &self,
+        mut request: HttpRequest,
+        token_source: &F,
+        handle: H,
+    ) -> Result<HttpResponse, Error>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<Option<SecretString>, Error>>,
+        H: Fn(&RequestContext, HttpResponse) -> Result<HttpResponse, Error>,
+    {
+        let endpoint: Option<String> = self
+            .metrics
+            .as_ref()
+            .map(|_| endpoint_template(self.resource_path(&request.url)));
+
+        let mut attempt: u32 = 0;
+
+        loop {
+            let mut context = RequestContext {
+                method: request.method.clone(),
+                url: request.url.clone(),
+                elapsed: Duration::ZERO,
+            };
+
+            let started_at: Instant = Instant::now();
+            let res: Result<HttpResponse, Error> = self.send(request.clone()).await;
+            context.elapsed = started_at.elapsed();
+
+            if let (Some(metrics), Some(endpoint)) = (&self.metrics, &endpoint) {
+                let status: StatusClass = match &res {
+                    Ok(response) => StatusClass::from(response.status),
+                    Err(..) => StatusClass::TransportError,
+                };
+                metrics.record_request(endpoint, status, context.elapsed);
+            }
+
+            let res: Result<HttpResponse, Error> = match res {
+                Ok(response) => {
+                    for middleware in self.middlewares.iter() {
+                        middleware.after_receive(&context, &response);
+                    }
+
+                    #[cfg(feature = "tracing")]
+                    {
+                        let span = tracing::Span::current();
+                        span.record("status", response.status.as_u16());
+                        span.record("latency_ms", context.elapsed.as_millis() as u64);
+                        if let Some(request_id) = response.request_id() {
+                            span.record("request_id", request_id);
+                        }
+                        tracing::debug!("response received");
+                    }
+
+                    if let Some(policy) = &self.retry {
+                        if policy.should_retry(attempt, response.status) {
+                            let delay: Duration = policy.delay(attempt, response.retry_after());
+
+                            if let (Some(metrics), Some(endpoint)) = (&self.metrics, &endpoint) {
+                                if response.status == StatusCode::TOO_MANY_REQUESTS {
+                                    metrics.record_rate_limit_wait(endpoint, delay);
+                                } else {
+                                    metrics.record_retry(endpoint);
+                                }
+                            }
+
+                            #[cfg(feature = "tracing")]
+                            if response.status == StatusCode::TOO_MANY_REQUESTS {
+                                tracing::warn!(
+                                    attempt,
+                                    delay_ms = delay.as_millis() as u64,
+                                    "rate limited, waiting"
+                                );
+                            } else {
+                                tracing::warn!(
+                                    attempt,
+                                    status = response.status.as_u16(),
+                                    delay_ms = delay.as_millis() as u64,
+                                    "server error, retrying"
+                                );
+                            }
+
+                            tokio::time::sleep(delay).await;
+
+                            // The token may have expired while waiting
+                            if let Some(token) = token_source().await? {
+                                authorize(&mut request, &token)?;
+                            }
+
+                            attempt += 1;
+                            continue;
+                        }
+                    }
+
+                    handle(&context, response)
+                }
+                Err(e) => Err(e.with_context(ErrorContext::new(
+                    context.method.clone(),
+                    self.resource_path(&context.url),
+                    None,
+                ))),
+            };
+
+            if let Err(e) = &res {
+                for middleware in self.middlewares.iter() {
+                    middleware.on_error(&context, e);
+                }
+
+                #[cfg(feature = "tracing")]
+                tracing::debug!(error = %e.inner(), "request failed");
+            }
+
+            return res;
+        }
+    }
+
+    /// Sends the request with the transport, or through the cassette.
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, Error> {
+        match &self.cassette {
+            Some(cassette) => match cassette.mode() {
+                CassetteMode::Record => {
+                    let recorded = RecordedRequest::new(&request);
+                    let response: HttpResponse = self.transport.send(request).await?;
+                    cassette.record_response(recorded, &response)?;
+                    Ok(response)
+                }
+                CassetteMode::Replay => cassette.replay_request(&request),
+            },
+            None => self.transport.send(request).await,
+        }
+    }
+}
+
+/// Transport sending the requests through the pipeline of the agent (middlewares, metrics, cassette and retries)
+#[derive(Debug)]
+struct Pipeline(HttpClientAgent);
+
+impl HttpTransport for Pipeline {
+    fn send(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, Error>> {
+        Box::pin(self.0.dispatch(request))
     }
 }
 
+/// Check if the API rejected the OAuth2 access token (i.e., revoked before its expiration).
+fn is_token_rejected(error: &Error) -> bool {
+    match error.inner() {
+        Error::Coinbase(e) if matches!(e.id.as_str(), "invalid_token" | "expired_token") => true,
+        _ => error.status() == Some(401),
+    }
+}
+
+/// Sets the bearer token of the request.
+fn authorize(request: &mut HttpRequest, token: &SecretString) -> Result<(), Error> {
+    let bearer: Zeroizing<String> = Zeroizing::new(format!("Bearer {}", token.expose_secret()));
+    let mut value: HeaderValue =
+        HeaderValue::from_str(&bearer).map_err(|e| Error::Transport(e.to_string()))?;
+    // Redacted by the `Debug` implementations, so never logged
+    value.set_sensitive(true);
+    request.headers.insert(AUTHORIZATION, value);
+    Ok(())
+}
+
+/// Authentication mechanism of the agent
+#[derive(Debug, Clone)]
+enum Authenticator {
+    /// JWT generator, for the API keys, with the optional cache
+    Jwt(JwtGenerator, Option<Arc<JwtCache>>),
+    /// OAuth2 session
+    OAuth2(Arc<OAuth2Session>),
+}
+
 #[derive(Debug, Clone)]
 pub struct SecureHttpClientAgent {
-    /// JWT generator, disabled in sandbox mode.
-    jwt: Option<Jwt>,
+    /// Authentication mechanism, JWT is disabled in sandbox mode.
+    authenticator: Option<Authenticator>,
+    /// Root URL for the JWT `uri` claim, which must be the Coinbase API host, even behind a proxy.
+    jwt_root_url: Url,
     /// Base client that is responsible for making the requests.
     base: HttpClientAgent,
 }
 
 impl SecureHttpClientAgent {
-    pub(super) fn new(auth: CoinbaseAuth, sandbox: bool, timeout: Duration) -> Result<Self, Error> {
-        let jwt: Option<Jwt> = match auth {
+    pub(super) fn new(builder: CoinbaseAppClientBuilder) -> Result<Self, Error> {
+        let sandbox: bool = builder.sandbox;
+        let base: HttpClientAgent = HttpClientAgent::new(&builder)?;
+
+        let jwt_cache = || {
+            builder
+                .jwt_cache
+                .map(|margin| Arc::new(JwtCache::new(margin)))
+        };
+
+        let authenticator: Option<Authenticator> = match builder.auth {
             CoinbaseAuth::None => None,
             CoinbaseAuth::ApiKeys {
                 api_key,
@@ -105,41 +402,485 @@ impl SecureHttpClientAgent {
                 if sandbox {
                     None
                 } else {
-                    Some(Jwt::new(api_key, secret_key)?)
+                    Some(Authenticator::Jwt(
+                        JwtGenerator::new(api_key, secret_key)?,
+                        jwt_cache(),
+                    ))
                 }
             }
+            CoinbaseAuth::Signer(signer) => {
+                // Do not generate JWT in sandbox mode.
+                if sandbox {
+                    None
+                } else {
+                    Some(Authenticator::Jwt(
+                        JwtGenerator::with_signer(signer),
+                        jwt_cache(),
+                    ))
+                }
+            }
+            CoinbaseAuth::Jwt(jwt) => {
+                // Do not generate JWT in sandbox mode.
+                if sandbox {
+                    None
+                } else {
+                    Some(Authenticator::Jwt(jwt, jwt_cache()))
+                }
+            }
+            // The session refreshes the tokens through the same pipeline
+            CoinbaseAuth::OAuth2(oauth2) => Some(Authenticator::OAuth2(Arc::new(
+                OAuth2Session::new(oauth2, Arc::new(Pipeline(base.clone()))),
+            ))),
         };
 
         Ok(Self {
-            jwt,
-            base: HttpClientAgent::new(sandbox, timeout)?,
+            authenticator,
+            jwt_root_url: Url::parse(API_ROOT_URL)?,
+            base,
         })
     }
 
     /// Builds a token for the request.
     ///
-    /// If JWT is not enabled, returns `None`.
-    fn build_token(&self, method: &Method, path: &str) -> Result<Option<String>, Error> {
-        match &self.jwt {
-            Some(jwt) => {
-                let url: Url = self.base.root_url.join(path)?;
-                let uri: String = Jwt::build_uri(method, &url)?;
-                Ok(Some(jwt.encode(Some(uri))?))
+    /// If authentication is not enabled, returns `None`.
+    async fn build_token(
+        &self,
+        method: &Method,
+        path: &str,
+    ) -> Result<Option<SecretString>, Error> {
+        match &self.authenticator {
+            Some(Authenticator::Jwt(jwt, cache)) => {
+                let url: Url = self.jwt_root_url.join(path)?;
+                let uri: String = JwtGenerator::build_uri(method, &url)?;
+
+                let encode = |uri: String| async move {
+                    let started_at: Instant = Instant::now();
+                    let token: Result<String, Error> = jwt.encode(Some(uri)).await;
+
+                    if let Some(metrics) = &self.base.metrics {
+                        metrics.record_jwt_signing(started_at.elapsed());
+                    }
+
+                    token
+                };
+
+                let token: SecretString = match cache {
+                    Some(cache) => cache.get_or_encode(jwt.lifetime(), uri, encode).await?,
+                    None => SecretString::from(encode(uri).await?),
+                };
+
+                Ok(Some(token))
             }
+            Some(Authenticator::OAuth2(session)) => Ok(Some(session.access_token().await?)),
             None => Ok(None),
         }
     }
 
-    pub(super) async fn get(&self, resource: &str, query: Option<&str>) -> Result<Response, Error> {
+    /// Metrics recorder, if any
+    #[inline]
+    pub(super) fn metrics(&self) -> Option<&Arc<dyn MetricsRecorder>> {
+        self.base.metrics.as_ref()
+    }
+
+    /// JWT cache statistics, if enabled
+    pub(super) fn jwt_cache_stats(&self) -> Option<JwtCacheStats> {
+        match &self.authenticator {
+            Some(Authenticator::Jwt(_, Some(cache))) => Some(cache.stats()),
+            _ => None,
+        }
+    }
+
+    pub(super) async fn get(
+        &self,
+        resource: &str,
+        query: Option<&str>,
+    ) -> Result<HttpResponse, Error> {
         const METHOD: Method = Method::GET;
 
         // Build URL
         let url: Url = self.base.build_url(resource, query)?;
 
-        // Build token
-        let token: Option<String> = self.build_token(&METHOD, resource)?;
+        // The last token sent, to refresh it if the API rejects it
+        let sent: &Mutex<Option<SecretString>> = &Mutex::new(None);
+
+        let token_source = || async move {
+            let token: Option<SecretString> = self.build_token(&METHOD, resource).await?;
+            *sent.lock().expect("token lock poisoned") = token.clone();
+            Ok(token)
+        };
+
+        // Execute request, with a token built for every attempt
+        let res: Result<HttpResponse, Error> = self
+            .base
+            .execute_request(METHOD, url.clone(), None, &token_source)
+            .await;
+
+        // Refresh the OAuth2 token once, if it has been rejected before its expiration
+        if let (Some(Authenticator::OAuth2(session)), Err(e)) = (&self.authenticator, &res) {
+            if is_token_rejected(e) {
+                let rejected: Option<SecretString> =
+                    sent.lock().expect("token lock poisoned").take();
+
+                if let Some(rejected) = rejected {
+                    session.refresh_rejected(&rejected).await?;
+
+                    return self
+                        .base
+                        .execute_request(METHOD, url, None, &token_source)
+                        .await;
+                }
+            }
+        }
+
+        res
+    }
+
+    /// Sends a `GET` request and deserializes the JSON response.
+    ///
+    /// The decoding errors include the request context and the body.
+    pub(super) async fn get_json<T>(&self, resource: &str, query: Option<&str>) -> Result<T, Error>
+    where
+        T: DeserializeOwned,
+    {
+        let response: HttpResponse = self.get(resource, query).await?;
+
+        response
+            .json()
+            .map_err(|e| e.with_context(ErrorContext::new(Method::GET, resource, Some(&response))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use base64::Engine;
+    use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+    use http::StatusCode;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::app::auth::oauth2::{
+        MemoryTokenStore, OAuth2, OAuth2Config, OAuth2Token, TokenStore,
+    };
+    use crate::app::constant::OAUTH2_TOKEN_URL;
+
+    /// In-memory transport, returning the responses in order, then the last one to every request.
+    #[derive(Debug)]
+    struct MemoryTransport {
+        responses: Mutex<Vec<(StatusCode, &'static str)>>,
+        requests: Mutex<Vec<HttpRequest>>,
+    }
+
+    impl MemoryTransport {
+        fn new(status: StatusCode, body: &'static str) -> Arc<Self> {
+            Self::sequence(&[(status, body)])
+        }
+
+        fn sequence(responses: &[(StatusCode, &'static str)]) -> Arc<Self> {
+            Arc::new(Self {
+                responses: Mutex::new(responses.to_vec()),
+                requests: Mutex::new(Vec::new()),
+            })
+        }
+    }
+
+    impl HttpTransport for MemoryTransport {
+        fn send(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, Error>> {
+            self.requests.lock().unwrap().push(request);
+
+            let mut responses = self.responses.lock().unwrap();
+            let (status, body) = match responses.len() {
+                1 => responses[0],
+                _ => responses.remove(0),
+            };
+
+            Box::pin(async move {
+                Ok(HttpResponse {
+                    status,
+                    headers: http::HeaderMap::new(),
+                    body: body.as_bytes().to_vec(),
+                })
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_jwt_uri_behind_proxy() {
+        let transport = MemoryTransport::new(StatusCode::OK, "{}");
+
+        let auth = CoinbaseAuth::ApiKeys {
+            api_key: String::from("organizations/org/apiKeys/key"),
+            secret_key: STANDARD.encode([7u8; 32]).into(),
+        };
+        let builder = CoinbaseAppClientBuilder::default()
+            .auth(auth)
+            .base_url(Url::parse("http://localhost:8080/coinbase").unwrap())
+            .transport(transport.clone());
+        let agent = SecureHttpClientAgent::new(builder).unwrap();
+
+        agent.get("/v2/accounts", Some("limit=100")).await.unwrap();
+
+        let request: HttpRequest = transport.requests.lock().unwrap().remove(0);
+        assert_eq!(
+            request.url.as_str(),
+            "http://localhost:8080/coinbase/v2/accounts?limit=100"
+        );
+        assert_eq!(agent.base.resource_path(&request.url), "/v2/accounts");
+
+        let token: &str = request.headers[AUTHORIZATION]
+            .to_str()
+            .unwrap()
+            .strip_prefix("Bearer ")
+            .unwrap();
+        let payload: &str = token.split('.').nth(1).unwrap();
+        let payload: Value =
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).unwrap()).unwrap();
+        assert_eq!(payload["uri"], "GET api.coinbase.com/v2/accounts");
+    }
+
+    #[tokio::test]
+    async fn test_error_envelope() {
+        let transport = MemoryTransport::new(
+            StatusCode::NOT_FOUND,
+            r#"{"errors":[{"id":"not_found","message":"Not found"}]}"#,
+        );
+        let builder = CoinbaseAppClientBuilder::default().transport(transport);
+        let agent = SecureHttpClientAgent::new(builder).unwrap();
+
+        let error: Error = agent.get("/v2/accounts/unknown", None).await.unwrap_err();
+        match error.inner() {
+            Error::Coinbase(e) => assert_eq!(e.id, "not_found"),
+            e => panic!("unexpected error: {e:?}"),
+        }
+        assert_eq!(error.status(), Some(404));
+        assert_eq!(error.context().unwrap().path, "/v2/accounts/unknown");
+
+        let transport = MemoryTransport::new(StatusCode::BAD_GATEWAY, "<html></html>");
+        let builder = CoinbaseAppClientBuilder::default().transport(transport);
+        let agent = SecureHttpClientAgent::new(builder).unwrap();
+
+        let error: Error = agent.get("/v2/accounts", None).await.unwrap_err();
+        assert!(matches!(error.inner(), Error::HttpStatus(502)));
+        assert!(error.is_retryable());
+
+        // Decoding error
+        let transport = MemoryTransport::new(StatusCode::OK, r#"{"data":"#);
+        let builder = CoinbaseAppClientBuilder::default().transport(transport);
+        let agent = SecureHttpClientAgent::new(builder).unwrap();
+
+        let error: Error = agent
+            .get_json::<Value>("/v2/accounts?limit=100", None)
+            .await
+            .unwrap_err();
+        assert!(matches!(error.inner(), Error::Decode { .. }));
+        let context: &ErrorContext = error.context().unwrap();
+        assert_eq!(context.path, "/v2/accounts");
+        assert_eq!(context.body.as_deref(), Some(r#"{"data":"#));
+    }
+
+    /// Adds the 2FA header and records the statuses.
+    #[derive(Debug, Default)]
+    struct AuditMiddleware {
+        statuses: Mutex<Vec<u16>>,
+        errors: Mutex<Vec<String>>,
+    }
+
+    impl Middleware for AuditMiddleware {
+        fn before_send(&self, request: &mut HttpRequest) -> Result<(), Error> {
+            request
+                .headers
+                .insert("CB-2FA-TOKEN", HeaderValue::from_static("123456"));
+            Ok(())
+        }
+
+        fn after_receive(&self, _context: &RequestContext, response: &HttpResponse) {
+            self.statuses.lock().unwrap().push(response.status.as_u16());
+        }
+
+        fn on_error(&self, _context: &RequestContext, error: &Error) {
+            self.errors.lock().unwrap().push(error.to_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_middleware() {
+        let transport = MemoryTransport::new(StatusCode::TOO_MANY_REQUESTS, "");
+        let middleware = Arc::new(AuditMiddleware::default());
+
+        let builder = CoinbaseAppClientBuilder::default()
+            .transport(transport.clone())
+            .middleware(middleware.clone());
+        let agent = SecureHttpClientAgent::new(builder).unwrap();
+
+        assert!(agent.get("/v2/accounts", None).await.is_err());
+
+        let request: HttpRequest = transport.requests.lock().unwrap().remove(0);
+        assert_eq!(request.headers["CB-2FA-TOKEN"], "123456");
+        assert_eq!(*middleware.statuses.lock().unwrap(), [429]);
+        assert_eq!(
+            *middleware.errors.lock().unwrap(),
+            ["GET /v2/accounts: HTTP status 429"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_oauth2_token_rejected() {
+        let transport = MemoryTransport::sequence(&[
+            (
+                StatusCode::UNAUTHORIZED,
+                r#"{"errors":[{"id":"revoked_token","message":"The access token was revoked"}]}"#,
+            ),
+            (
+                StatusCode::OK,
+                r#"{"access_token":"access-2","refresh_token":"refresh-2","expires_in":3600}"#,
+            ),
+            (StatusCode::OK, "{}"),
+        ]);
+        let recorder = Arc::new(MemoryRecorder::default());
+
+        let store = Arc::new(MemoryTokenStore::new(OAuth2Token {
+            access_token: SecretString::from("access-1"),
+            refresh_token: Some(SecretString::from("refresh-1")),
+            expires_at: Some(crate::util::time::now() + 3600),
+            scope: None,
+        }));
+        let auth = CoinbaseAuth::OAuth2(OAuth2 {
+            config: OAuth2Config {
+                client_id: String::from("client-id"),
+                client_secret: SecretString::from("client-secret"),
+                redirect_uri: Url::parse("https://example.com/callback").unwrap(),
+                scopes: Vec::new(),
+            },
+            store: store.clone(),
+        });
+        let builder = CoinbaseAppClientBuilder::default()
+            .auth(auth)
+            .transport(transport.clone())
+            .metrics(recorder.clone());
+        let agent = SecureHttpClientAgent::new(builder).unwrap();
+
+        // Refreshed once, then retried with the new token
+        agent.get("/v2/accounts", None).await.unwrap();
+
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0].headers[AUTHORIZATION], "Bearer access-1");
+        assert_eq!(requests[1].url.as_str(), OAUTH2_TOKEN_URL);
+        assert_eq!(requests[2].headers[AUTHORIZATION], "Bearer access-2");
+        assert_eq!(
+            store.load().unwrap().unwrap().access_token.expose_secret(),
+            "access-2"
+        );
+
+        // The refresh went through the pipeline
+        assert_eq!(
+            *recorder.requests.lock().unwrap(),
+            [
+                (String::from("/v2/accounts"), StatusClass::ClientError),
+                (String::from("/oauth2/token"), StatusClass::Success),
+                (String::from("/v2/accounts"), StatusClass::Success)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bearer_redacted() {
+        let transport = MemoryTransport::new(StatusCode::OK, "{}");
+
+        let auth = CoinbaseAuth::ApiKeys {
+            api_key: String::from("organizations/org/apiKeys/key"),
+            secret_key: STANDARD.encode([7u8; 32]).into(),
+        };
+        let builder = CoinbaseAppClientBuilder::default()
+            .auth(auth)
+            .transport(transport.clone());
+        let agent = SecureHttpClientAgent::new(builder).unwrap();
+
+        agent.get("/v2/accounts", None).await.unwrap();
+
+        let request: HttpRequest = transport.requests.lock().unwrap().remove(0);
+        let token: &str = request.headers[AUTHORIZATION].to_str().unwrap();
+        assert!(token.starts_with("Bearer "));
+        assert!(!format!("{request:?}").contains(token));
+    }
+
+    /// Records the requests and the JWT signatures.
+    #[derive(Debug, Default)]
+    struct MemoryRecorder {
+        requests: Mutex<Vec<(String, StatusClass)>>,
+        signatures: Mutex<usize>,
+    }
+
+    impl MetricsRecorder for MemoryRecorder {
+        fn record_request(&self, endpoint: &str, status: StatusClass, _latency: Duration) {
+            self.requests
+                .lock()
+                .unwrap()
+                .push((endpoint.to_string(), status));
+        }
+
+        fn record_jwt_signing(&self, _duration: Duration) {
+            *self.signatures.lock().unwrap() += 1;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        let transport = MemoryTransport::new(StatusCode::OK, "{}");
+        let recorder = Arc::new(MemoryRecorder::default());
+
+        let auth = CoinbaseAuth::ApiKeys {
+            api_key: String::from("organizations/org/apiKeys/key"),
+            secret_key: STANDARD.encode([7u8; 32]).into(),
+        };
+        let builder = CoinbaseAppClientBuilder::default()
+            .auth(auth)
+            .transport(transport)
+            .jwt_cache(Duration::from_secs(30))
+            .metrics(recorder.clone());
+        let agent = SecureHttpClientAgent::new(builder).unwrap();
+
+        agent.get("/v2/accounts/btc", None).await.unwrap();
+        agent.get("/v2/accounts/btc", None).await.unwrap();
+
+        assert_eq!(
+            *recorder.requests.lock().unwrap(),
+            [
+                (String::from("/v2/accounts/:id"), StatusClass::Success),
+                (String::from("/v2/accounts/:id"), StatusClass::Success)
+            ]
+        );
+        // The second token comes from the cache
+        assert_eq!(*recorder.signatures.lock().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retry_signs_again() {
+        let transport = MemoryTransport::new(StatusCode::SERVICE_UNAVAILABLE, "");
+        let recorder = Arc::new(MemoryRecorder::default());
+
+        let auth = CoinbaseAuth::ApiKeys {
+            api_key: String::from("organizations/org/apiKeys/key"),
+            secret_key: STANDARD.encode([7u8; 32]).into(),
+        };
+        let builder = CoinbaseAppClientBuilder::default()
+            .auth(auth)
+            .transport(transport.clone())
+            .retry(RetryPolicy::new(2).base_delay(Duration::ZERO))
+            .metrics(recorder.clone());
+        let agent = SecureHttpClientAgent::new(builder).unwrap();
+
+        assert!(agent.get("/v2/accounts", None).await.is_err());
 
-        // Execute request
-        self.base.execute_request(METHOD, url, None, token).await
+        // A new token for every attempt
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert!(
+            requests
+                .iter()
+                .all(|r| r.headers.contains_key(AUTHORIZATION))
+        );
+        assert_eq!(*recorder.signatures.lock().unwrap(), 3);
     }
 }
@@ -0,0 +1,246 @@
+//! JWT cache
+//!
+//! Every JWT is bound to a `(method, host+path)` URI and valid for 2 minutes:
+//! the cache reuses the tokens instead of signing a new one for every request.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use super::secret::SecretString;
+use crate::app::error::Error;
+use crate::util::time;
+
+/// JWT cache statistics
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct JwtCacheStats {
+    /// Tokens reused
+    pub hits: u64,
+    /// Tokens minted
+    pub misses: u64,
+}
+
+impl JwtCacheStats {
+    /// Ratio of reused tokens, between `0.0` and `1.0`
+    pub fn hit_rate(&self) -> f64 {
+        let total: u64 = self.hits + self.misses;
+
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CachedToken {
+    /// Zeroized when dropped from the cache.
+    token: SecretString,
+    /// UNIX timestamp after which the token must not be reused.
+    reuse_until: u64,
+}
+
+/// JWT cache, keyed by URI
+#[derive(Debug)]
+pub(crate) struct JwtCache {
+    /// Stop reusing a token this long before its expiration.
+    margin: Duration,
+    tokens: Mutex<HashMap<String, CachedToken>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl JwtCache {
+    pub(crate) fn new(margin: Duration) -> Self {
+        Self {
+            margin,
+            tokens: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Get a cached token for the URI, or mint a new one with `encode`.
+    ///
+    /// The lock is not held while signing, so a slow signer does not block the other requests.
+    pub(crate) async fn get_or_encode<F, Fut>(
+        &self,
+        lifetime: Duration,
+        uri: String,
+        encode: F,
+    ) -> Result<SecretString, Error>
+    where
+        F: FnOnce(String) -> Fut,
+        Fut: Future<Output = Result<String, Error>>,
+    {
+        let now: u64 = time::now();
+
+        {
+            let tokens = self.tokens.lock().expect("jwt cache lock poisoned");
+
+            if let Some(cached) = tokens.get(&uri) {
+                if cached.reuse_until > now {
+                    self.hits.fetch_add(1, Ordering::Relaxed);
+
+                    #[cfg(feature = "tracing")]
+                    tracing::trace!(uri = %uri, "JWT cache hit");
+
+                    return Ok(cached.token.clone());
+                }
+            }
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        #[cfg(feature = "tracing")]
+        tracing::trace!(uri = %uri, "JWT cache miss");
+
+        // Take the timestamp before signing: the token expires a bit later than this.
+        let reuse_until: u64 = (now + lifetime.as_secs()).saturating_sub(self.margin.as_secs());
+        let token: SecretString = SecretString::from(encode(uri.clone()).await?);
+
+        let mut tokens = self.tokens.lock().expect("jwt cache lock poisoned");
+
+        // Drop the expired tokens
+        tokens.retain(|_, cached| cached.reuse_until > now);
+        tokens.insert(
+            uri,
+            CachedToken {
+                token: token.clone(),
+                reuse_until,
+            },
+        );
+
+        Ok(token)
+    }
+
+    pub(crate) fn stats(&self) -> JwtCacheStats {
+        JwtCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+
+    use super::*;
+    use crate::app::auth::jwt::JwtGenerator;
+    use crate::app::auth::signer::LocalSigner;
+    use crate::app::transport::BoxFuture;
+
+    fn jwt() -> JwtGenerator {
+        // Raw Ed25519 seed
+        let secret: String = STANDARD.encode([7u8; 32]);
+        let signer = LocalSigner::new("organizations/org/apiKeys/key", secret).unwrap();
+        JwtGenerator::with_signer(Arc::new(signer))
+    }
+
+    fn encode<'a>(
+        jwt: &'a JwtGenerator,
+    ) -> impl FnOnce(String) -> BoxFuture<'a, Result<String, Error>> {
+        |uri| Box::pin(jwt.encode(Some(uri)))
+    }
+
+    #[tokio::test]
+    async fn test_reuse_until_margin() {
+        let jwt = jwt();
+
+        let cache = JwtCache::new(Duration::from_secs(30));
+        let first = cache
+            .get_or_encode(
+                jwt.lifetime(),
+                String::from("GET api.coinbase.com/v2/accounts"),
+                encode(&jwt),
+            )
+            .await
+            .unwrap();
+        let second = cache
+            .get_or_encode(
+                jwt.lifetime(),
+                String::from("GET api.coinbase.com/v2/accounts"),
+                encode(&jwt),
+            )
+            .await
+            .unwrap();
+        let other = cache
+            .get_or_encode(
+                jwt.lifetime(),
+                String::from("GET api.coinbase.com/v2/user"),
+                encode(&jwt),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+        assert_eq!(cache.stats(), JwtCacheStats { hits: 1, misses: 2 });
+        assert!((cache.stats().hit_rate() - 1.0 / 3.0).abs() < f64::EPSILON);
+
+        // A margin covering the whole lifetime disables the reuse
+        let cache = JwtCache::new(jwt.lifetime());
+        cache
+            .get_or_encode(
+                jwt.lifetime(),
+                String::from("GET api.coinbase.com/v2/accounts"),
+                encode(&jwt),
+            )
+            .await
+            .unwrap();
+        cache
+            .get_or_encode(
+                jwt.lifetime(),
+                String::from("GET api.coinbase.com/v2/accounts"),
+                encode(&jwt),
+            )
+            .await
+            .unwrap();
+        assert_eq!(cache.stats().hits, 0);
+    }
+
+    #[tokio::test]
+    async fn test_sign_without_lock() {
+        let jwt = jwt();
+        let cache = JwtCache::new(Duration::from_secs(30));
+
+        // Signing another URI while signing would deadlock if the lock was held
+        let token = cache
+            .get_or_encode(
+                jwt.lifetime(),
+                String::from("GET api.coinbase.com/v2/accounts"),
+                |uri| async {
+                    cache
+                        .get_or_encode(
+                            jwt.lifetime(),
+                            String::from("GET api.coinbase.com/v2/user"),
+                            encode(&jwt),
+                        )
+                        .await?;
+                    jwt.encode(Some(uri)).await
+                },
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(cache.stats().misses, 2);
+        assert_eq!(
+            cache
+                .get_or_encode(
+                    jwt.lifetime(),
+                    String::from("GET api.coinbase.com/v2/accounts"),
+                    encode(&jwt),
+                )
+                .await
+                .unwrap(),
+            token
+        );
+    }
+}
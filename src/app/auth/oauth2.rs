@@ -0,0 +1,650 @@
+//! Coinbase App OAuth2 authentication
+//!
+//! <https://docs.cdp.coinbase.com/coinbase-app/authentication-authorization/oauth2/integrations>
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use http::Method;
+use http::header::{CONTENT_TYPE, HeaderValue};
+use ring::digest::{self, SHA256};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex as AsyncMutex;
+use url::{Url, form_urlencoded};
+
+use super::secret::SecretString;
+use crate::app::constant::{OAUTH2_AUTHORIZE_URL, OAUTH2_REVOKE_URL, OAUTH2_TOKEN_URL};
+use crate::app::error::Error;
+#[cfg(feature = "reqwest")]
+use crate::app::transport::ReqwestTransport;
+use crate::app::transport::{HttpRequest, HttpResponse, HttpTransport};
+use crate::util::time;
+
+/// Refresh the access token when it expires in less than this (secs).
+const REFRESH_MARGIN: u64 = 60;
+
+/// OAuth2 application configuration
+#[derive(Clone)]
+pub struct OAuth2Config {
+    /// Client ID
+    pub client_id: String,
+    /// Client secret
+    pub client_secret: SecretString,
+    /// Redirect URI, as registered in the application settings
+    pub redirect_uri: Url,
+    /// Requested scopes (i.e., `wallet:accounts:read`)
+    pub scopes: Vec<String>,
+}
+
+impl fmt::Debug for OAuth2Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OAuth2Config")
+            .field("client_id", &self.client_id)
+            .field("redirect_uri", &self.redirect_uri)
+            .field("scopes", &self.scopes)
+            .finish()
+    }
+}
+
+/// Proof Key for Code Exchange (S256)
+///
+/// <https://datatracker.ietf.org/doc/html/rfc7636>
+#[derive(Clone, PartialEq, Eq)]
+pub struct Pkce {
+    /// Code verifier, to send with the code exchange
+    pub verifier: String,
+    /// Code challenge, to send with the authorization request
+    pub challenge: String,
+}
+
+impl fmt::Debug for Pkce {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pkce")
+            .field("challenge", &self.challenge)
+            .finish()
+    }
+}
+
+impl Pkce {
+    /// Generate a random code verifier and its challenge
+    pub fn generate() -> Result<Self, Error> {
+        // 32 random bytes, resulting in 43 Base64 characters
+        let mut bytes: [u8; 32] = [0u8; 32];
+        SystemRandom::new()
+            .fill(&mut bytes)
+            .map_err(|why| Error::OAuth2(why.to_string()))?;
+
+        Ok(Self::from_verifier(URL_SAFE_NO_PAD.encode(bytes)))
+    }
+
+    /// Compute the challenge of an existing code verifier
+    pub fn from_verifier<S>(verifier: S) -> Self
+    where
+        S: Into<String>,
+    {
+        let verifier: String = verifier.into();
+        let challenge: String =
+            URL_SAFE_NO_PAD.encode(digest::digest(&SHA256, verifier.as_bytes()));
+
+        Self {
+            verifier,
+            challenge,
+        }
+    }
+}
+
+/// OAuth2 token
+///
+/// `Debug` and `Serialize` redact the tokens: the [`TokenStore`]s persist [`OAuth2Token::expose_for_storage`].
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OAuth2Token {
+    /// Access token
+    pub access_token: SecretString,
+    /// Refresh token
+    pub refresh_token: Option<SecretString>,
+    /// Expiration, UNIX timestamp
+    pub expires_at: Option<u64>,
+    /// Granted scopes
+    pub scope: Option<String>,
+}
+
+impl fmt::Debug for OAuth2Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OAuth2Token")
+            .field("access_token", &self.access_token)
+            .field("refresh_token", &self.refresh_token)
+            .field("expires_at", &self.expires_at)
+            .field("scope", &self.scope)
+            .finish()
+    }
+}
+
+impl OAuth2Token {
+    /// Token with the secrets in clear, to persist it
+    ///
+    /// The serialized form deserializes back into an [`OAuth2Token`].
+    pub fn expose_for_storage(&self) -> ExposedOAuth2Token<'_> {
+        ExposedOAuth2Token {
+            access_token: self.access_token.expose_secret(),
+            refresh_token: self.refresh_token.as_ref().map(SecretString::expose_secret),
+            expires_at: self.expires_at,
+            scope: self.scope.as_deref(),
+        }
+    }
+
+    /// Check if the token expires in less than `margin` seconds.
+    #[inline]
+    fn expires_within(&self, margin: u64) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= time::now() + margin)
+    }
+}
+
+/// OAuth2 token with the secrets in clear (see [`OAuth2Token::expose_for_storage`])
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ExposedOAuth2Token<'a> {
+    /// Access token
+    pub access_token: &'a str,
+    /// Refresh token
+    pub refresh_token: Option<&'a str>,
+    /// Expiration, UNIX timestamp
+    pub expires_at: Option<u64>,
+    /// Granted scopes
+    pub scope: Option<&'a str>,
+}
+
+/// Storage for the OAuth2 tokens
+///
+/// Refresh tokens are rotated: the store must persist the new token on every refresh.
+/// Use [`OAuth2Token::expose_for_storage`] to serialize the secrets in clear.
+pub trait TokenStore: fmt::Debug + Send + Sync {
+    /// Load the current token
+    fn load(&self) -> Result<Option<OAuth2Token>, Error>;
+
+    /// Replace the current token
+    fn store(&self, token: OAuth2Token) -> Result<(), Error>;
+}
+
+/// In-memory token store
+#[derive(Debug, Default)]
+pub struct MemoryTokenStore {
+    token: Mutex<Option<OAuth2Token>>,
+}
+
+impl MemoryTokenStore {
+    /// New store with an initial token
+    pub fn new(token: OAuth2Token) -> Self {
+        Self {
+            token: Mutex::new(Some(token)),
+        }
+    }
+}
+
+impl TokenStore for MemoryTokenStore {
+    fn load(&self) -> Result<Option<OAuth2Token>, Error> {
+        Ok(self.token.lock().expect("token lock poisoned").clone())
+    }
+
+    fn store(&self, token: OAuth2Token) -> Result<(), Error> {
+        *self.token.lock().expect("token lock poisoned") = Some(token);
+        Ok(())
+    }
+}
+
+/// OAuth2 authentication
+#[derive(Debug, Clone)]
+pub struct OAuth2 {
+    /// Application configuration
+    pub config: OAuth2Config,
+    /// Token storage
+    pub store: Arc<dyn TokenStore>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: SecretString,
+    refresh_token: Option<SecretString>,
+    expires_in: Option<u64>,
+    scope: Option<String>,
+}
+
+impl From<TokenResponse> for OAuth2Token {
+    fn from(res: TokenResponse) -> Self {
+        Self {
+            access_token: res.access_token,
+            refresh_token: res.refresh_token,
+            expires_at: res.expires_in.map(|secs| time::now() + secs),
+            scope: res.scope,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: String,
+    error_description: Option<String>,
+}
+
+/// OAuth2 client, for the authorization flow
+#[derive(Debug, Clone)]
+pub struct OAuth2Client {
+    config: OAuth2Config,
+    transport: Arc<dyn HttpTransport>,
+}
+
+impl OAuth2Client {
+    /// New OAuth2 client
+    #[cfg(feature = "reqwest")]
+    pub fn new(config: OAuth2Config) -> Self {
+        Self::with_transport(config, Arc::new(ReqwestTransport::default()))
+    }
+
+    /// New OAuth2 client, with a custom HTTP transport
+    pub fn with_transport(config: OAuth2Config, transport: Arc<dyn HttpTransport>) -> Self {
+        Self { config, transport }
+    }
+
+    /// Construct the URL to redirect the user to.
+    ///
+    /// The `state` must be verified when the user is redirected back to the application.
+    ///
+    /// <https://docs.cdp.coinbase.com/coinbase-app/authentication-authorization/oauth2/reference#get-oauth2auth>
+    pub fn authorization_url(&self, state: &str, pkce: Option<&Pkce>) -> Result<Url, Error> {
+        let mut url: Url = Url::parse(OAUTH2_AUTHORIZE_URL)?;
+
+        {
+            let mut query = url.query_pairs_mut();
+            query
+                .append_pair("response_type", "code")
+                .append_pair("client_id", &self.config.client_id)
+                .append_pair("redirect_uri", self.config.redirect_uri.as_str())
+                .append_pair("state", state);
+
+            if !self.config.scopes.is_empty() {
+                query.append_pair("scope", &self.config.scopes.join(","));
+            }
+
+            if let Some(pkce) = pkce {
+                query
+                    .append_pair("code_challenge", &pkce.challenge)
+                    .append_pair("code_challenge_method", "S256");
+            }
+        }
+
+        Ok(url)
+    }
+
+    /// Exchange the authorization code for a token
+    ///
+    /// `code_verifier` is required if PKCE has been used for the authorization request.
+    pub async fn exchange_code(
+        &self,
+        code: &str,
+        code_verifier: Option<&str>,
+    ) -> Result<OAuth2Token, Error> {
+        let mut form: Vec<(&str, &str)> = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("client_id", &self.config.client_id),
+            ("client_secret", self.config.client_secret.expose_secret()),
+            ("redirect_uri", self.config.redirect_uri.as_str()),
+        ];
+
+        if let Some(code_verifier) = code_verifier {
+            form.push(("code_verifier", code_verifier));
+        }
+
+        self.request_token(&form).await
+    }
+
+    /// Get a new token with a refresh token
+    ///
+    /// The refresh token is single-use: the returned token contains the new one.
+    pub async fn refresh(&self, refresh_token: &str) -> Result<OAuth2Token, Error> {
+        let form: [(&str, &str); 4] = [
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+            ("client_id", &self.config.client_id),
+            ("client_secret", self.config.client_secret.expose_secret()),
+        ];
+
+        self.request_token(&form).await
+    }
+
+    /// Revoke an access token
+    pub async fn revoke(&self, token: &str) -> Result<(), Error> {
+        let form: [(&str, &str); 3] = [
+            ("token", token),
+            ("client_id", &self.config.client_id),
+            ("client_secret", self.config.client_secret.expose_secret()),
+        ];
+
+        self.post_form(OAUTH2_REVOKE_URL, &form).await?;
+
+        Ok(())
+    }
+
+    async fn request_token(&self, form: &[(&str, &str)]) -> Result<OAuth2Token, Error> {
+        let res: TokenResponse = self.post_form(OAUTH2_TOKEN_URL, form).await?.json()?;
+        Ok(res.into())
+    }
+
+    async fn post_form(&self, url: &str, form: &[(&str, &str)]) -> Result<HttpResponse, Error> {
+        let body: String = form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(form)
+            .finish();
+
+        let mut request: HttpRequest = HttpRequest::new(Method::POST, Url::parse(url)?);
+        request.headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/x-www-form-urlencoded"),
+        );
+        request.body = Some(body.into_bytes());
+
+        let res: HttpResponse = self.transport.send(request).await?;
+        handle_response(res)
+    }
+}
+
+fn handle_response(res: HttpResponse) -> Result<HttpResponse, Error> {
+    if res.status.is_success() {
+        return Ok(res);
+    }
+
+    let status = res.status;
+
+    match res.json::<ErrorResponse>() {
+        Ok(ErrorResponse {
+            error,
+            error_description: Some(description),
+        }) => Err(Error::OAuth2(format!("{error}: {description}"))),
+        Ok(ErrorResponse { error, .. }) => Err(Error::OAuth2(error)),
+        Err(..) => Err(Error::OAuth2(status.to_string())),
+    }
+}
+
+/// OAuth2 session of the HTTP agent
+#[derive(Debug)]
+pub(crate) struct OAuth2Session {
+    client: OAuth2Client,
+    store: Arc<dyn TokenStore>,
+    /// Avoid concurrent refreshes, since refresh tokens are single-use.
+    refresh: AsyncMutex<()>,
+}
+
+impl OAuth2Session {
+    pub(crate) fn new(auth: OAuth2, transport: Arc<dyn HttpTransport>) -> Self {
+        Self {
+            client: OAuth2Client::with_transport(auth.config, transport),
+            store: auth.store,
+            refresh: AsyncMutex::new(()),
+        }
+    }
+
+    /// Get a valid access token, refreshing it if it's about to expire.
+    pub(crate) async fn access_token(&self) -> Result<SecretString, Error> {
+        let token: OAuth2Token = self.load()?;
+
+        if !token.expires_within(REFRESH_MARGIN) {
+            return Ok(token.access_token);
+        }
+
+        self.refresh(|token| token.expires_within(REFRESH_MARGIN))
+            .await
+    }
+
+    /// Refresh the token after the API rejected it (i.e., revoked before its expiration).
+    pub(crate) async fn refresh_rejected(
+        &self,
+        rejected: &SecretString,
+    ) -> Result<SecretString, Error> {
+        self.refresh(|token| &token.access_token == rejected).await
+    }
+
+    /// Refresh the token, if it's still `stale` once the other refreshes are done.
+    async fn refresh<F>(&self, stale: F) -> Result<SecretString, Error>
+    where
+        F: Fn(&OAuth2Token) -> bool,
+    {
+        let _guard = self.refresh.lock().await;
+
+        // Another request may have refreshed the token in the meantime
+        let token: OAuth2Token = self.load()?;
+
+        if !stale(&token) {
+            return Ok(token.access_token);
+        }
+
+        let refresh_token: &str = token
+            .refresh_token
+            .as_ref()
+            .map(SecretString::expose_secret)
+            .ok_or_else(|| Error::OAuth2(String::from("token expired and no refresh token")))?;
+
+        let mut new_token: OAuth2Token = self.client.refresh(refresh_token).await?;
+
+        // Keep the previous refresh token if a new one hasn't been issued
+        if new_token.refresh_token.is_none() {
+            new_token.refresh_token = token.refresh_token;
+        }
+
+        let access_token: SecretString = new_token.access_token.clone();
+        self.store.store(new_token)?;
+
+        Ok(access_token)
+    }
+
+    fn load(&self) -> Result<OAuth2Token, Error> {
+        self.store
+            .load()?
+            .ok_or_else(|| Error::OAuth2(String::from("no token available")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use http::{HeaderMap, StatusCode};
+
+    use super::*;
+    use crate::app::transport::BoxFuture;
+
+    /// Transport returning the queued responses, in order.
+    #[derive(Debug, Default)]
+    struct StubTransport {
+        responses: Mutex<VecDeque<(StatusCode, &'static str)>>,
+        requests: Mutex<Vec<HttpRequest>>,
+    }
+
+    impl StubTransport {
+        fn new(responses: &[(StatusCode, &'static str)]) -> Arc<Self> {
+            Arc::new(Self {
+                responses: Mutex::new(responses.iter().copied().collect()),
+                requests: Mutex::new(Vec::new()),
+            })
+        }
+
+        /// Form of the request at `index`
+        fn form(&self, index: usize) -> Vec<(String, String)> {
+            let requests = self.requests.lock().unwrap();
+            form_urlencoded::parse(requests[index].body.as_deref().unwrap())
+                .into_owned()
+                .collect()
+        }
+    }
+
+    impl HttpTransport for StubTransport {
+        fn send(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, Error>> {
+            self.requests.lock().unwrap().push(request);
+            let (status, body) = self
+                .responses
+                .lock()
+                .unwrap()
+                .pop_front()
+                .expect("no response left");
+
+            Box::pin(async move {
+                Ok(HttpResponse {
+                    status,
+                    headers: HeaderMap::new(),
+                    body: body.as_bytes().to_vec(),
+                })
+            })
+        }
+    }
+
+    fn config() -> OAuth2Config {
+        OAuth2Config {
+            client_id: String::from("client-id"),
+            client_secret: SecretString::from("client-secret"),
+            redirect_uri: Url::parse("https://example.com/callback").unwrap(),
+            scopes: Vec::new(),
+        }
+    }
+
+    fn pair(key: &str, value: &str) -> (String, String) {
+        (key.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn test_pkce_challenge() {
+        // RFC 7636, Appendix B
+        let pkce = Pkce::from_verifier("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk");
+        assert_eq!(
+            pkce.challenge,
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+
+        let pkce = Pkce::generate().unwrap();
+        assert_eq!(pkce.verifier.len(), 43);
+        assert_eq!(pkce, Pkce::from_verifier(pkce.verifier.clone()));
+    }
+
+    #[cfg(feature = "reqwest")]
+    #[test]
+    fn test_authorization_url() {
+        let client = OAuth2Client::new(OAuth2Config {
+            client_id: String::from("client-id"),
+            client_secret: SecretString::from("client-secret"),
+            redirect_uri: Url::parse("https://example.com/callback").unwrap(),
+            scopes: vec![
+                String::from("wallet:accounts:read"),
+                String::from("wallet:transactions:read"),
+            ],
+        });
+
+        let pkce = Pkce::from_verifier("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk");
+        let url = client.authorization_url("xyz", Some(&pkce)).unwrap();
+
+        assert_eq!(
+            url.as_str(),
+            "https://login.coinbase.com/oauth2/auth?response_type=code&client_id=client-id&redirect_uri=https%3A%2F%2Fexample.com%2Fcallback&state=xyz&scope=wallet%3Aaccounts%3Aread%2Cwallet%3Atransactions%3Aread&code_challenge=E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM&code_challenge_method=S256"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_exchange_and_revoke() {
+        let transport = StubTransport::new(&[
+            (
+                StatusCode::OK,
+                r#"{"access_token":"access-1","refresh_token":"refresh-1","expires_in":3600,"scope":"wallet:accounts:read"}"#,
+            ),
+            (StatusCode::OK, "{}"),
+            (
+                StatusCode::BAD_REQUEST,
+                r#"{"error":"invalid_grant","error_description":"The code has expired"}"#,
+            ),
+        ]);
+        let client = OAuth2Client::with_transport(config(), transport.clone());
+
+        let token: OAuth2Token = client
+            .exchange_code("code", Some("verifier"))
+            .await
+            .unwrap();
+        assert_eq!(token.access_token.expose_secret(), "access-1");
+        assert_eq!(
+            token
+                .refresh_token
+                .as_ref()
+                .map(SecretString::expose_secret),
+            Some("refresh-1")
+        );
+        assert!(!format!("{token:?}").contains("access-1"));
+
+        // Redacted, unless exposed for storage
+        let json: String = serde_json::to_string(&token).unwrap();
+        assert!(!json.contains("access-1") && !json.contains("refresh-1"));
+        let json: String = serde_json::to_string(&token.expose_for_storage()).unwrap();
+        assert!(json.contains(r#""refresh_token":"refresh-1""#));
+        assert_eq!(serde_json::from_str::<OAuth2Token>(&json).unwrap(), token);
+        assert!(token.expires_at.unwrap() > time::now() + 3500);
+
+        let form = transport.form(0);
+        assert!(form.contains(&pair("grant_type", "authorization_code")));
+        assert!(form.contains(&pair("code", "code")));
+        assert!(form.contains(&pair("code_verifier", "verifier")));
+        assert!(form.contains(&pair("client_secret", "client-secret")));
+
+        client.revoke("access-1").await.unwrap();
+        assert_eq!(
+            transport.requests.lock().unwrap()[1].url.as_str(),
+            OAUTH2_REVOKE_URL
+        );
+        assert!(transport.form(1).contains(&pair("token", "access-1")));
+
+        let error: Error = client.exchange_code("code", None).await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "oauth2: invalid_grant: The code has expired"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_session_refresh() {
+        let transport = StubTransport::new(&[(
+            StatusCode::OK,
+            r#"{"access_token":"access-2","refresh_token":"refresh-2","expires_in":3600}"#,
+        )]);
+        let store = Arc::new(MemoryTokenStore::new(OAuth2Token {
+            access_token: SecretString::from("access-1"),
+            refresh_token: Some(SecretString::from("refresh-1")),
+            // Expires within the refresh margin
+            expires_at: Some(time::now() + 10),
+            scope: None,
+        }));
+        let session = OAuth2Session::new(
+            OAuth2 {
+                config: config(),
+                store: store.clone(),
+            },
+            transport.clone(),
+        );
+
+        let access_token: SecretString = session.access_token().await.unwrap();
+        assert_eq!(access_token.expose_secret(), "access-2");
+        assert!(
+            transport
+                .form(0)
+                .contains(&pair("refresh_token", "refresh-1"))
+        );
+
+        // The rotated refresh token replaces the old one
+        let token: OAuth2Token = store.load().unwrap().unwrap();
+        assert_eq!(
+            token
+                .refresh_token
+                .as_ref()
+                .map(SecretString::expose_secret),
+            Some("refresh-2")
+        );
+
+        // Still valid, not refreshed again
+        let access_token: SecretString = session.access_token().await.unwrap();
+        assert_eq!(access_token.expose_secret(), "access-2");
+        assert_eq!(transport.requests.lock().unwrap().len(), 1);
+    }
+}
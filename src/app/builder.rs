@@ -1,10 +1,22 @@
 //! Coinbase App client builder
 
+use std::sync::Arc;
 use std::time::Duration;
 
+#[cfg(feature = "reqwest")]
+use reqwest::Client;
+use url::Url;
+
 use super::auth::CoinbaseAuth;
+use super::cassette::Cassette;
 use super::client::CoinbaseAppClient;
 use super::error::Error;
+use super::metrics::MetricsRecorder;
+use super::middleware::Middleware;
+use super::retry::RetryPolicy;
+use super::transport::HttpTransport;
+#[cfg(feature = "reqwest")]
+use super::transport::ReqwestTransport;
 
 /// Coinbase App client builder
 #[derive(Debug, Clone)]
@@ -15,6 +27,20 @@ pub struct CoinbaseAppClientBuilder {
     pub sandbox: bool,
     /// Requests timeout
     pub timeout: Duration,
+    /// Reuse the JWTs until this margin before their expiration (default: disabled)
+    pub jwt_cache: Option<Duration>,
+    /// Custom base URL (i.e., a proxy or a mock server)
+    pub base_url: Option<Url>,
+    /// Custom HTTP transport (default: [`ReqwestTransport`](super::transport::ReqwestTransport))
+    pub transport: Option<Arc<dyn HttpTransport>>,
+    /// Record or replay the requests
+    pub cassette: Option<Arc<Cassette>>,
+    /// Middlewares, called in this order around every request
+    pub middlewares: Vec<Arc<dyn Middleware>>,
+    /// Retry policy (default: disabled)
+    pub retry: Option<RetryPolicy>,
+    /// Metrics recorder
+    pub metrics: Option<Arc<dyn MetricsRecorder>>,
 }
 
 impl Default for CoinbaseAppClientBuilder {
@@ -23,6 +49,13 @@ impl Default for CoinbaseAppClientBuilder {
             auth: CoinbaseAuth::default(),
             sandbox: false,
             timeout: Duration::from_secs(20),
+            jwt_cache: None,
+            base_url: None,
+            transport: None,
+            cassette: None,
+            middlewares: Vec::new(),
+            retry: None,
+            metrics: None,
         }
     }
 }
@@ -49,6 +82,71 @@ impl CoinbaseAppClientBuilder {
         self
     }
 
+    /// Enable the JWT cache
+    ///
+    /// The tokens are reused for the same method and path, until `margin` before their expiration.
+    #[inline]
+    pub fn jwt_cache(mut self, margin: Duration) -> Self {
+        self.jwt_cache = Some(margin);
+        self
+    }
+
+    /// Set a custom base URL (i.e., a proxy or a mock server)
+    ///
+    /// Its path is kept as a prefix of the API paths (i.e., `https://proxy/coinbase/v2/accounts`).
+    /// The JWTs are still issued for the Coinbase API host, so a proxy can forward them as they are.
+    #[inline]
+    pub fn base_url(mut self, base_url: Url) -> Self {
+        self.base_url = Some(base_url);
+        self
+    }
+
+    /// Set a custom HTTP client (i.e., for connection pooling, custom TLS roots or outbound proxies)
+    ///
+    /// The [`timeout`](Self::timeout) is ignored: it must be configured in the client.
+    #[cfg(feature = "reqwest")]
+    #[inline]
+    pub fn http_client(self, client: Client) -> Self {
+        self.transport(Arc::new(ReqwestTransport::from(client)))
+    }
+
+    /// Set a custom HTTP transport
+    ///
+    /// The [`timeout`](Self::timeout) is ignored: it must be handled by the transport.
+    #[inline]
+    pub fn transport(mut self, transport: Arc<dyn HttpTransport>) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// Record or replay the requests (see [`Cassette`])
+    #[inline]
+    pub fn cassette(mut self, cassette: Arc<Cassette>) -> Self {
+        self.cassette = Some(cassette);
+        self
+    }
+
+    /// Add a middleware (see [`Middleware`])
+    #[inline]
+    pub fn middleware(mut self, middleware: Arc<dyn Middleware>) -> Self {
+        self.middlewares.push(middleware);
+        self
+    }
+
+    /// Retry the rate-limited and server error responses (see [`RetryPolicy`])
+    #[inline]
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Set a metrics recorder (see [`MetricsRecorder`])
+    #[inline]
+    pub fn metrics(mut self, recorder: Arc<dyn MetricsRecorder>) -> Self {
+        self.metrics = Some(recorder);
+        self
+    }
+
     /// Build client
     #[inline]
     pub fn build(self) -> Result<CoinbaseAppClient, Error> {
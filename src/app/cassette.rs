@@ -0,0 +1,468 @@
+//! HTTP cassettes
+//!
+//! Record the request/response pairs to a file, and replay them later without hitting the network
+//! (i.e., to reproduce a deserialization bug with a production payload).
+//!
+//! The credential headers (including the ones marked as sensitive) and the token-like JSON fields
+//! are redacted before writing.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use http::StatusCode;
+use http::header::{CONTENT_LENGTH, HeaderMap, HeaderName, HeaderValue, TRANSFER_ENCODING};
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+
+use super::error::Error;
+use super::transport::{HttpRequest, HttpResponse};
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Headers redacted, in addition to the sensitive ones
+const REDACTED_HEADERS: [&str; 4] = ["authorization", "cookie", "set-cookie", "cb-2fa-token"];
+
+/// JSON fields redacted from the response bodies
+const REDACTED_FIELDS: [&str; 7] = [
+    "access_token",
+    "refresh_token",
+    "id_token",
+    "client_secret",
+    "private_key",
+    "privateKey",
+    "jwt",
+];
+
+/// Cassette mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CassetteMode {
+    /// Send the requests and record the interactions
+    Record,
+    /// Serve the recorded interactions, without sending the requests
+    Replay,
+}
+
+/// Recorded request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    /// HTTP method
+    pub method: String,
+    /// Path and query (i.e., `/v2/accounts?limit=100`)
+    pub uri: String,
+    /// Headers, redacted
+    pub headers: Vec<(String, String)>,
+}
+
+/// Recorded response
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    /// HTTP status code
+    pub status: u16,
+    /// Headers, redacted
+    pub headers: Vec<(String, String)>,
+    /// Body, redacted
+    pub body: String,
+}
+
+/// Recorded request/response pair
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Interaction {
+    /// Request
+    pub request: RecordedRequest,
+    /// Response
+    pub response: RecordedResponse,
+}
+
+#[derive(Debug, Default)]
+struct Tape {
+    interactions: Vec<Interaction>,
+    /// Interactions already replayed
+    played: Vec<bool>,
+}
+
+/// HTTP cassette
+#[derive(Debug)]
+pub struct Cassette {
+    path: PathBuf,
+    mode: CassetteMode,
+    tape: Mutex<Tape>,
+}
+
+impl Cassette {
+    /// Record the interactions to a file
+    ///
+    /// The file is overwritten after every interaction.
+    pub fn record<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            mode: CassetteMode::Record,
+            tape: Mutex::new(Tape::default()),
+        }
+    }
+
+    /// Replay the interactions recorded in a file
+    pub fn replay<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let json: String = fs::read_to_string(path.as_ref())?;
+        let interactions: Vec<Interaction> = serde_json::from_str(&json)?;
+
+        Ok(Self {
+            path: path.as_ref().to_path_buf(),
+            mode: CassetteMode::Replay,
+            tape: Mutex::new(Tape {
+                played: vec![false; interactions.len()],
+                interactions,
+            }),
+        })
+    }
+
+    /// Mode
+    #[inline]
+    pub fn mode(&self) -> CassetteMode {
+        self.mode
+    }
+
+    /// File path
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Recorded interactions
+    pub fn interactions(&self) -> Vec<Interaction> {
+        self.lock().interactions.clone()
+    }
+
+    /// Records the response of a request.
+    pub(crate) fn record_response(
+        &self,
+        request: RecordedRequest,
+        response: &HttpResponse,
+    ) -> Result<(), Error> {
+        let body: String = String::from_utf8_lossy(&response.body).into_owned();
+
+        let interaction = Interaction {
+            request,
+            response: RecordedResponse {
+                status: response.status.as_u16(),
+                headers: redact_headers(&response.headers),
+                body: redact_body(&body),
+            },
+        };
+
+        let mut tape = self.lock();
+        tape.interactions.push(interaction);
+        tape.played.push(true);
+
+        let json: String = serde_json::to_string_pretty(&tape.interactions)?;
+        fs::write(&self.path, json)?;
+
+        Ok(())
+    }
+
+    /// Returns the recorded response of a request.
+    ///
+    /// The interactions are replayed in order: when all the matching ones have been played, the last one is reused.
+    pub(crate) fn replay_request(&self, request: &HttpRequest) -> Result<HttpResponse, Error> {
+        let method: &str = request.method.as_str();
+        let uri: String = path_and_query(request);
+
+        let mut tape = self.lock();
+        let Tape {
+            interactions,
+            played,
+        } = &mut *tape;
+
+        let matching: Vec<usize> = interactions
+            .iter()
+            .enumerate()
+            .filter(|(_, i)| i.request.method == method && i.request.uri == uri)
+            .map(|(index, _)| index)
+            .collect();
+
+        let index: usize = matching
+            .iter()
+            .find(|index| !played[**index])
+            .or_else(|| matching.last())
+            .copied()
+            .ok_or_else(|| Error::Cassette(format!("no interaction for {method} {uri}")))?;
+
+        played[index] = true;
+
+        let response: &RecordedResponse = &interactions[index].response;
+
+        let mut headers: HeaderMap = HeaderMap::new();
+        for (name, value) in response.headers.iter() {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(value),
+            ) {
+                headers.append(name, value);
+            }
+        }
+
+        Ok(HttpResponse {
+            status: StatusCode::from_u16(response.status)
+                .map_err(|e| Error::Cassette(e.to_string()))?,
+            headers,
+            body: response.body.clone().into_bytes(),
+        })
+    }
+
+    #[inline]
+    fn lock(&self) -> std::sync::MutexGuard<'_, Tape> {
+        self.tape.lock().expect("cassette lock poisoned")
+    }
+}
+
+impl RecordedRequest {
+    pub(crate) fn new(request: &HttpRequest) -> Self {
+        Self {
+            method: request.method.to_string(),
+            uri: path_and_query(request),
+            headers: redact_headers(&request.headers),
+        }
+    }
+}
+
+#[inline]
+fn path_and_query(request: &HttpRequest) -> String {
+    let url = &request.url;
+
+    match url.query() {
+        Some(query) => format!("{}?{query}", url.path()),
+        None => url.path().to_string(),
+    }
+}
+
+fn redact_headers(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        // The body may change with the redaction
+        .filter(|(name, _)| *name != CONTENT_LENGTH && *name != TRANSFER_ENCODING)
+        .map(|(name, value)| {
+            let value: &str = if value.is_sensitive() || REDACTED_HEADERS.contains(&name.as_str()) {
+                REDACTED
+            } else {
+                value.to_str().unwrap_or(REDACTED)
+            };
+
+            (name.to_string(), value.to_string())
+        })
+        .collect()
+}
+
+/// Redacts the token-like fields of a JSON body.
+///
+/// Only the redacted values are rewritten: the rest of the body is kept byte for byte
+/// (i.e., the key order and the precision of the numbers).
+fn redact_body(body: &str) -> String {
+    // Not JSON: keep as it is
+    if serde_json::from_str::<IgnoredAny>(body).is_err() {
+        return body.to_string();
+    }
+
+    let bytes: &[u8] = body.as_bytes();
+    let mut redacted: String = String::with_capacity(body.len());
+    // End of the part already copied
+    let mut copied: usize = 0;
+    let mut pos: usize = 0;
+
+    while pos < bytes.len() {
+        if bytes[pos] != b'"' {
+            pos += 1;
+            continue;
+        }
+
+        let end: usize = string_end(bytes, pos + 1);
+        let key: &str = &body[pos + 1..end];
+        pos = end + 1;
+
+        // A string followed by a colon is a key
+        let Some(value) = body[pos..].trim_start().strip_prefix(':') else {
+            continue;
+        };
+
+        if !REDACTED_FIELDS.contains(&key) {
+            continue;
+        }
+
+        let value_start: usize = body.len() - value.trim_start().len();
+        let mut values = serde_json::Deserializer::from_str(&body[value_start..])
+            .into_iter::<Option<IgnoredAny>>();
+
+        if let Some(Ok(value)) = values.next() {
+            let value_end: usize = value_start + values.byte_offset();
+
+            if value.is_some() {
+                redacted.push_str(&body[copied..value_start]);
+                redacted.push('"');
+                redacted.push_str(REDACTED);
+                redacted.push('"');
+                copied = value_end;
+            }
+
+            pos = value_end;
+        }
+    }
+
+    redacted.push_str(&body[copied..]);
+    redacted
+}
+
+/// Index of the closing quote of the JSON string starting at `pos`.
+fn string_end(bytes: &[u8], mut pos: usize) -> usize {
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'\\' => pos += 2,
+            b'"' => return pos,
+            _ => pos += 1,
+        }
+    }
+
+    bytes.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+
+    #[test]
+    fn test_redact_body() {
+        let body =
+            r#"{"data":{"id":"1","access_token":"secret","nested":[{"refresh_token":"secret"}]}}"#;
+        let redacted: Value = serde_json::from_str(&redact_body(body)).unwrap();
+        assert_eq!(redacted["data"]["id"], "1");
+        assert_eq!(redacted["data"]["access_token"], REDACTED);
+        assert_eq!(redacted["data"]["nested"][0]["refresh_token"], REDACTED);
+
+        assert_eq!(redact_body("not json"), "not json");
+    }
+
+    #[test]
+    fn test_redact_body_keeps_bytes() {
+        // Unsorted keys, high-precision numbers and escapes
+        let body = r#"{"zeta":1,"amount":0.100000000000000000000001,"big":123456789012345678901234567890,"text":"a\"b","alpha":{"refresh_token":null}}"#;
+        assert!(serde_json::from_str::<Value>(body).is_ok());
+        assert_eq!(redact_body(body), body);
+
+        let body = r#"{"zeta": 1.50, "access_token" : "secret", "nested": [{"jwt": {"a": 1}, "id": 0.30}]}"#;
+        assert_eq!(
+            redact_body(body),
+            r#"{"zeta": 1.50, "access_token" : "[REDACTED]", "nested": [{"jwt": "[REDACTED]", "id": 0.30}]}"#
+        );
+    }
+
+    #[test]
+    fn test_record_without_secrets() {
+        let path: PathBuf = std::env::temp_dir().join(format!(
+            "coinbase-api-cassette-secrets-{}.json",
+            std::process::id()
+        ));
+
+        let mut request = HttpRequest::new(
+            http::Method::GET,
+            url::Url::parse("https://api.coinbase.com/v2/accounts").unwrap(),
+        );
+        let mut custom = HeaderValue::from_static("custom-secret");
+        custom.set_sensitive(true);
+        request.headers.insert("x-custom", custom);
+        request.headers.insert(
+            "authorization",
+            HeaderValue::from_static("Bearer jwt-secret"),
+        );
+        request
+            .headers
+            .insert("cb-2fa-token", HeaderValue::from_static("2fa-secret"));
+        request
+            .headers
+            .insert("cb-version", HeaderValue::from_static("2024-01-01"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("set-cookie", HeaderValue::from_static("cookie-secret"));
+        let response = HttpResponse {
+            status: StatusCode::OK,
+            headers,
+            body: br#"{"access_token":"token-secret"}"#.to_vec(),
+        };
+
+        let cassette = Cassette::record(&path);
+        cassette
+            .record_response(RecordedRequest::new(&request), &response)
+            .unwrap();
+
+        let json: String = fs::read_to_string(&path).unwrap();
+        for secret in [
+            "custom-secret",
+            "jwt-secret",
+            "2fa-secret",
+            "cookie-secret",
+            "token-secret",
+        ] {
+            assert!(!json.contains(secret), "{secret} recorded");
+        }
+        assert!(json.contains("2024-01-01"));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(all(feature = "reqwest", feature = "testing"))]
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        use std::sync::Arc;
+
+        use base64::Engine;
+        use base64::engine::general_purpose::STANDARD;
+
+        use crate::app::auth::CoinbaseAuth;
+        use crate::app::client::CoinbaseAppClient;
+        use crate::testing::fixtures;
+        use crate::testing::server::MockServer;
+
+        let path: PathBuf =
+            std::env::temp_dir().join(format!("coinbase-api-cassette-{}.json", std::process::id()));
+
+        let auth = CoinbaseAuth::ApiKeys {
+            api_key: String::from("organizations/org/apiKeys/key"),
+            secret_key: STANDARD.encode([7u8; 32]).into(),
+        };
+
+        // Record
+        let server = MockServer::start().await.unwrap();
+        server.add_account(fixtures::account("btc", "BTC", "0.5"));
+
+        let client = CoinbaseAppClient::builder()
+            .auth(auth)
+            .base_url(server.url().clone())
+            .cassette(Arc::new(Cassette::record(&path)))
+            .build()
+            .unwrap();
+        let recorded = client.accounts().await.unwrap();
+        drop(server);
+
+        let json: String = fs::read_to_string(&path).unwrap();
+        assert!(json.contains(REDACTED));
+        assert!(!json.contains("Bearer"));
+
+        // Replay, without server
+        let client = CoinbaseAppClient::builder()
+            .base_url(url::Url::parse("http://127.0.0.1:9").unwrap())
+            .cassette(Arc::new(Cassette::replay(&path).unwrap()))
+            .build()
+            .unwrap();
+        let replayed = client.accounts().await.unwrap();
+        assert_eq!(replayed.len(), recorded.len());
+        assert_eq!(replayed[0].id, "btc");
+        assert!(client.account("unknown").await.is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
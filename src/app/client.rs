@@ -1,12 +1,27 @@
 //! Coinbase App client
 
-use reqwest::Response;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use url::form_urlencoded;
 
 use super::agent::SecureHttpClientAgent;
 use super::auth::CoinbaseAuth;
+use super::auth::cache::JwtCacheStats;
 use super::error::Error;
-use super::response::{Account, CoinbaseResponse, Transaction};
+use super::metrics::endpoint_template;
+use super::portfolio::Portfolio;
+use super::response::{Account, CoinbaseResponse, ExchangeRates, Transaction};
 use crate::app::builder::CoinbaseAppClientBuilder;
+use crate::util::de;
+
+/// Direction of the pagination
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PageDirection {
+    /// Follow the `next_uri` (older items)
+    Next,
+    /// Follow the `previous_uri` (newer items)
+    Previous,
+}
 
 /// Coinbase App client
 #[derive(Debug, Clone)]
@@ -29,42 +44,32 @@ impl CoinbaseAppClient {
     #[inline]
     pub(super) fn from_builder(builder: CoinbaseAppClientBuilder) -> Result<Self, Error> {
         Ok(Self {
-            client: SecureHttpClientAgent::new(builder.auth, builder.sandbox, builder.timeout)?,
+            client: SecureHttpClientAgent::new(builder)?,
         })
     }
 
+    /// Get the JWT cache statistics
+    ///
+    /// Returns `None` if the cache is not enabled.
+    #[inline]
+    pub fn jwt_cache_stats(&self) -> Option<JwtCacheStats> {
+        self.client.jwt_cache_stats()
+    }
+
     /// Get accounts
     ///
     /// <https://docs.cdp.coinbase.com/coinbase-app/track-apis/accounts#list-accounts>
+    #[inline]
     pub async fn accounts(&self) -> Result<Vec<Account>, Error> {
-        let mut accounts = Vec::new();
-
-        let mut next_uri: Option<String> = None;
-
-        loop {
-            let uri: &str = match &next_uri {
-                Some(next_uri) => next_uri.as_str(),
-                None => "/v2/accounts",
-            };
-
-            let res: Response = self.client.get(uri, Some("limit=100")).await?;
-
-            let res: CoinbaseResponse<Vec<Account>> = res.json().await?;
-
-            accounts.extend(res.data);
-
-            // Check if there is another page
-            if let Some(pagination) = res.pagination {
-                if let Some(next) = pagination.next_uri {
-                    next_uri = Some(next);
-                    continue;
-                }
-            }
-
-            break;
-        }
+        self.paginate("/v2/accounts").await
+    }
 
-        Ok(accounts)
+    /// Get accounts, decoding them one by one
+    ///
+    /// An account with an unexpected shape yields an error, without failing the others.
+    #[inline]
+    pub async fn accounts_lenient(&self) -> Result<Vec<Result<Account, Error>>, Error> {
+        self.paginate_lenient("/v2/accounts").await
     }
 
     /// Get account by ID
@@ -72,40 +77,177 @@ impl CoinbaseAppClient {
     /// <https://docs.cdp.coinbase.com/coinbase-app/track-apis/accounts#show-account>
     pub async fn account(&self, id: &str) -> Result<Account, Error> {
         let endpoint: String = format!("/v2/accounts/{id}");
-        let res: Response = self.client.get(&endpoint, None).await?;
-        let res: CoinbaseResponse<Account> = res.json().await?;
+        let res: CoinbaseResponse<Account> = self.client.get_json(&endpoint, None).await?;
         Ok(res.data)
     }
 
+    /// Get the exchange rates of a currency (i.e., `USD`)
+    ///
+    /// <https://docs.cdp.coinbase.com/coinbase-app/track-apis/exchange-rates#get-exchange-rates>
+    pub async fn exchange_rates(&self, currency: &str) -> Result<ExchangeRates, Error> {
+        let query: String = form_urlencoded::Serializer::new(String::new())
+            .append_pair("currency", currency)
+            .finish();
+        let res: CoinbaseResponse<ExchangeRates> = self
+            .client
+            .get_json("/v2/exchange-rates", Some(&query))
+            .await?;
+        Ok(res.data)
+    }
+
+    /// Get the value of all the accounts in a currency (i.e., `USD`)
+    ///
+    /// Lists the accounts and fetches the exchange rates once (see [`Portfolio`]).
+    pub async fn portfolio_value(&self, target: &str) -> Result<Portfolio, Error> {
+        let accounts: Vec<Account> = self.accounts().await?;
+        let rates: ExchangeRates = self.exchange_rates(target).await?;
+        Ok(Portfolio::new(&accounts, &rates))
+    }
+
     /// Get transactions by account ID
     ///
     /// <https://docs.cdp.coinbase.com/coinbase-app/track-apis/transactions#list-transactions>
     pub async fn transactions(&self, account_id: &str) -> Result<Vec<Transaction>, Error> {
-        let mut transactions = Vec::new();
+        let endpoint: String = format!("/v2/accounts/{account_id}/transactions");
+        self.paginate(&endpoint).await
+    }
+
+    /// Get the transactions of an account newer than a transaction, from the newest
+    ///
+    /// Uses the `ending_before` cursor, to fetch only the new transactions.
+    pub async fn transactions_since(
+        &self,
+        account_id: &str,
+        transaction_id: &str,
+    ) -> Result<Vec<Transaction>, Error> {
+        let endpoint: String = format!("/v2/accounts/{account_id}/transactions");
+        let query: String = form_urlencoded::Serializer::new(String::new())
+            .append_pair("limit", "100")
+            .append_pair("ending_before", transaction_id)
+            .finish();
+        self.paginate_with(&endpoint, &query, PageDirection::Previous)
+            .await
+    }
+
+    /// Get transaction by account ID and transaction ID
+    ///
+    /// <https://docs.cdp.coinbase.com/coinbase-app/track-apis/transactions#show-transaction>
+    pub async fn transaction(
+        &self,
+        account_id: &str,
+        transaction_id: &str,
+    ) -> Result<Transaction, Error> {
+        let endpoint: String = format!("/v2/accounts/{account_id}/transactions/{transaction_id}");
+        let res: CoinbaseResponse<Transaction> = self.client.get_json(&endpoint, None).await?;
+        Ok(res.data)
+    }
+
+    /// Get transactions by account ID, decoding them one by one
+    ///
+    /// A transaction with an unexpected shape yields an error, without failing the others.
+    pub async fn transactions_lenient(
+        &self,
+        account_id: &str,
+    ) -> Result<Vec<Result<Transaction, Error>>, Error> {
+        let endpoint: String = format!("/v2/accounts/{account_id}/transactions");
+        self.paginate_lenient(&endpoint).await
+    }
+
+    /// Get all the pages of a list endpoint, decoding the items one by one.
+    async fn paginate_lenient<T>(&self, endpoint: &str) -> Result<Vec<Result<T, Error>>, Error>
+    where
+        T: DeserializeOwned,
+    {
+        let values: Vec<Value> = self.paginate(endpoint).await?;
+
+        Ok(values
+            .into_iter()
+            .enumerate()
+            .map(|(index, value)| {
+                de::from_value(value).map_err(|e| match e {
+                    // Prefix with the position of the item in the list
+                    Error::Decode { path, source } => Error::Decode {
+                        path: format!("[{index}].{path}"),
+                        source,
+                    },
+                    e => e,
+                })
+            })
+            .collect())
+    }
 
-        let mut next_uri: Option<String> = None;
+    /// Get all the pages of a list endpoint.
+    #[inline]
+    async fn paginate<T>(&self, endpoint: &str) -> Result<Vec<T>, Error>
+    where
+        T: DeserializeOwned,
+    {
+        self.paginate_with(endpoint, "limit=100", PageDirection::Next)
+            .await
+    }
+
+    /// Get the pages of a list endpoint, following the cursors in a direction.
+    ///
+    /// The items are returned in the list order.
+    async fn paginate_with<T>(
+        &self,
+        endpoint: &str,
+        query: &str,
+        direction: PageDirection,
+    ) -> Result<Vec<T>, Error>
+    where
+        T: DeserializeOwned,
+    {
+        let mut items = Vec::new();
+
+        let mut page_uri: Option<String> = None;
+        let mut pages: usize = 0;
 
         loop {
-            let uri: String =
-                next_uri.unwrap_or_else(|| format!("/v2/accounts/{account_id}/transactions"));
+            pages += 1;
+
+            // The page URI already includes the query
+            let fut = match &page_uri {
+                Some(page_uri) => self.client.get_json(page_uri, None),
+                None => self.client.get_json(endpoint, Some(query)),
+            };
 
-            let res: Response = self.client.get(&uri, Some("limit=100")).await?;
+            #[cfg(feature = "tracing")]
+            let fut = {
+                let uri: &str = page_uri.as_deref().unwrap_or(endpoint);
+                tracing::Instrument::instrument(
+                    fut,
+                    tracing::debug_span!("page", page = pages, uri),
+                )
+            };
 
-            let res: CoinbaseResponse<Vec<Transaction>> = res.json().await?;
+            let res: CoinbaseResponse<Vec<T>> = fut.await?;
 
-            transactions.extend(res.data);
+            // The previous pages come before in the list
+            let uri: Option<String> = match direction {
+                PageDirection::Next => {
+                    items.extend(res.data);
+                    res.pagination.and_then(|p| p.next_uri)
+                }
+                PageDirection::Previous => {
+                    items.splice(0..0, res.data);
+                    res.pagination.and_then(|p| p.previous_uri)
+                }
+            };
 
             // Check if there is another page
-            if let Some(pagination) = res.pagination {
-                if let Some(next) = pagination.next_uri {
-                    next_uri = Some(next);
-                    continue;
-                }
+            if let Some(uri) = uri {
+                page_uri = Some(uri);
+                continue;
             }
 
             break;
         }
 
-        Ok(transactions)
+        if let Some(metrics) = self.client.metrics() {
+            metrics.record_pagination(&endpoint_template(endpoint), pages);
+        }
+
+        Ok(items)
     }
 }
@@ -2,6 +2,15 @@
 pub(super) const API_ROOT_URL: &str = "https://api.coinbase.com";
 pub(super) const API_SANDBOX_URL: &str = "https://api-sandbox.coinbase.com";
 
+/// OAuth2 endpoints
+pub(super) const OAUTH2_AUTHORIZE_URL: &str = "https://login.coinbase.com/oauth2/auth";
+pub(super) const OAUTH2_TOKEN_URL: &str = "https://login.coinbase.com/oauth2/token";
+pub(super) const OAUTH2_REVOKE_URL: &str = "https://login.coinbase.com/oauth2/revoke";
+
+/// Default environment variables for the API key credentials
+pub(super) const ENV_API_KEY_NAME: &str = "COINBASE_API_KEY_NAME";
+pub(super) const ENV_API_KEY_PRIVATE_KEY: &str = "COINBASE_API_KEY_PRIVATE_KEY";
+
 /// User Agent for the client
 pub(super) const USER_AGENT_NAME: &str =
     concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
@@ -0,0 +1,492 @@
+//! Cost basis and realized gains
+//!
+//! Tracks the acquired lots of every currency from the transaction history, using the native amount as the cost basis,
+//! and matches the disposals against them with a [`CostBasisMethod`].
+//!
+//! The transactions are classified by the sign of their amount: a credit (i.e., `buy`, `receive`, `earn_payout` or the
+//! credited side of a `trade`) opens a lot, a debit (i.e., `sell`, `send` or the debited side of a `trade`) is a disposal.
+//! The internal transfers (see [`TransactionType::is_internal_transfer`](super::response::TransactionType::is_internal_transfer))
+//! only move funds between the user's own accounts: since the lots are pooled per currency, they are skipped
+//! and never create a taxable event.
+//!
+//! A `send` may be a payment or a move to the user's own external wallet, which the history can't tell apart:
+//! by default it's a disposal, unless [`CostBasisCalculator::external_transfers`] treats the `send` and `receive`
+//! transactions as transfers.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::error::Error;
+use super::response::{Transaction, TransactionStatus, TransactionType};
+
+/// Relative tolerance of the amount comparisons, absorbing the floating point dust
+/// (i.e., `0.1 + 0.2` lots fully covering a `0.3` disposal)
+const DUST_RATIO: f64 = 1e-9;
+
+/// Lot matching method
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CostBasisMethod {
+    /// First in, first out
+    #[default]
+    Fifo,
+    /// Last in, first out
+    Lifo,
+    /// Highest (unit cost) in, first out
+    Hifo,
+    /// Average cost of all the open lots
+    Average,
+}
+
+impl CostBasisMethod {
+    /// Name (i.e., `fifo`)
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Fifo => "fifo",
+            Self::Lifo => "lifo",
+            Self::Hifo => "hifo",
+            Self::Average => "average",
+        }
+    }
+
+    /// Index of the next lot to dispose
+    fn next_lot(&self, lots: &[Lot]) -> Option<usize> {
+        match self {
+            Self::Fifo | Self::Average => (!lots.is_empty()).then_some(0),
+            Self::Lifo => lots.len().checked_sub(1),
+            // The earliest lot on ties
+            Self::Hifo => lots
+                .iter()
+                .enumerate()
+                .rev()
+                .max_by(|(_, a), (_, b)| a.unit_cost().total_cmp(&b.unit_cost()))
+                .map(|(index, _)| index),
+        }
+    }
+}
+
+impl fmt::Display for CostBasisMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for CostBasisMethod {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fifo" => Ok(Self::Fifo),
+            "lifo" => Ok(Self::Lifo),
+            "hifo" => Ok(Self::Hifo),
+            "average" => Ok(Self::Average),
+            _ => Err(Error::CostBasis(format!("unknown method: {s}"))),
+        }
+    }
+}
+
+/// Acquired lot (or the part of a lot matched by a disposal)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Lot {
+    /// ID of the acquiring transaction
+    pub transaction_id: String,
+    /// Currency code (i.e., BTC)
+    pub currency: String,
+    /// Acquisition time
+    pub acquired_at: DateTime<Utc>,
+    /// Amount
+    pub amount: f64,
+    /// Cost, in the native currency
+    pub cost: f64,
+}
+
+impl Lot {
+    /// Cost of a unit
+    #[inline]
+    pub fn unit_cost(&self) -> f64 {
+        if self.amount == 0.0 {
+            0.0
+        } else {
+            self.cost / self.amount
+        }
+    }
+}
+
+/// Disposal, matched against the open lots
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Disposal {
+    /// ID of the disposing transaction
+    pub transaction_id: String,
+    /// Currency code (i.e., BTC)
+    pub currency: String,
+    /// Disposal time
+    pub disposed_at: DateTime<Utc>,
+    /// Amount
+    pub amount: f64,
+    /// Proceeds, in the native currency
+    pub proceeds: f64,
+    /// Cost basis of the matched lots, in the native currency
+    pub cost: f64,
+    /// Realized gain (negative for a loss)
+    pub gain: f64,
+    /// Amount not covered by any open lot (i.e., acquired out of the history), with a zero cost basis
+    pub uncovered: f64,
+    /// Matched lots, with the disposed amount and cost
+    pub lots: Vec<Lot>,
+}
+
+/// Cost basis report
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CostBasisReport {
+    /// Lot matching method
+    pub method: CostBasisMethod,
+    /// Native currency of the costs and proceeds (`None` if there are no lots nor disposals)
+    pub currency: Option<String>,
+    /// Disposals, in chronological order
+    pub disposals: Vec<Disposal>,
+    /// Remaining open lots, by currency then acquisition time
+    pub open_lots: Vec<Lot>,
+    /// Total realized gain
+    pub realized_gain: f64,
+}
+
+/// Cost basis calculator
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CostBasisCalculator {
+    /// Lot matching method (default: FIFO)
+    pub method: CostBasisMethod,
+    /// Skip the `send` and `receive` transactions, like the internal transfers (default: false)
+    pub external_transfers: bool,
+}
+
+impl CostBasisCalculator {
+    /// Set lot matching method
+    #[inline]
+    pub fn method(mut self, method: CostBasisMethod) -> Self {
+        self.method = method;
+        self
+    }
+
+    /// Treat the `send` and `receive` transactions as transfers between the user's own wallets
+    ///
+    /// They are skipped instead of being disposals and acquisitions: the lots keep their cost basis.
+    #[inline]
+    pub fn external_transfers(mut self, external_transfers: bool) -> Self {
+        self.external_transfers = external_transfers;
+        self
+    }
+
+    /// Compute the realized gains and the open lots of the transactions
+    ///
+    /// The transactions can come from several accounts and be in any order. Only the completed ones are used,
+    /// and the ones in the native currency (i.e., fiat deposits) are skipped.
+    ///
+    /// Fails if the transactions have different native currencies.
+    pub fn compute<'a, I>(&self, transactions: I) -> Result<CostBasisReport, Error>
+    where
+        I: IntoIterator<Item = &'a Transaction>,
+    {
+        let mut transactions: Vec<&Transaction> = transactions
+            .into_iter()
+            .filter(|tx| tx.status == TransactionStatus::Completed)
+            .filter(|tx| !tx.r#type.is_internal_transfer())
+            .filter(|tx| {
+                !(self.external_transfers
+                    && matches!(tx.r#type, TransactionType::Send | TransactionType::Receive))
+            })
+            .filter(|tx| tx.amount.currency != tx.native_amount.currency)
+            .collect();
+        transactions.sort_by(|a, b| {
+            a.created_at
+                .cmp(&b.created_at)
+                .then_with(|| a.id.cmp(&b.id))
+        });
+
+        let mut currency: Option<String> = None;
+        let mut lots: BTreeMap<String, Vec<Lot>> = BTreeMap::new();
+        let mut disposals: Vec<Disposal> = Vec::new();
+
+        for transaction in transactions {
+            let native: &str = transaction.native_amount.currency.as_str();
+            match &currency {
+                Some(currency) if currency != native => {
+                    return Err(Error::CostBasis(format!(
+                        "mixed native currencies: {currency} and {native} (transaction {})",
+                        transaction.id
+                    )));
+                }
+                Some(_) => {}
+                None => currency = Some(native.to_string()),
+            }
+
+            let open: &mut Vec<Lot> = lots.entry(transaction.amount.currency.clone()).or_default();
+
+            if transaction.amount.amount > 0.0 {
+                open.push(Lot {
+                    transaction_id: transaction.id.clone(),
+                    currency: transaction.amount.currency.clone(),
+                    acquired_at: transaction.created_at,
+                    amount: transaction.amount.amount,
+                    cost: transaction.native_amount.amount.abs(),
+                });
+            } else if transaction.amount.amount < 0.0 {
+                disposals.push(self.dispose(transaction, open));
+            }
+        }
+
+        let open_lots: Vec<Lot> = lots.into_values().flatten().collect();
+
+        Ok(CostBasisReport {
+            method: self.method,
+            currency,
+            realized_gain: disposals.iter().map(|d| d.gain).sum(),
+            disposals,
+            open_lots,
+        })
+    }
+
+    fn dispose(&self, transaction: &Transaction, open: &mut Vec<Lot>) -> Disposal {
+        let amount: f64 = transaction.amount.amount.abs();
+        let proceeds: f64 = transaction.native_amount.amount.abs();
+
+        let dust: f64 = amount * DUST_RATIO;
+
+        let mut matched: Vec<Lot> = Vec::new();
+        let mut remaining: f64 = amount;
+
+        if self.method == CostBasisMethod::Average {
+            // Every open lot is reduced in proportion
+            let total: f64 = open.iter().map(|lot| lot.amount).sum();
+            let ratio: f64 = if total <= 0.0 {
+                0.0
+            } else if amount + dust >= total {
+                1.0
+            } else {
+                amount / total
+            };
+
+            for lot in open.iter_mut() {
+                let part = Lot {
+                    amount: lot.amount * ratio,
+                    cost: lot.cost * ratio,
+                    ..lot.clone()
+                };
+
+                // Nothing matched (i.e., no amount left)
+                if part.amount <= 0.0 {
+                    continue;
+                }
+
+                lot.amount -= part.amount;
+                lot.cost -= part.cost;
+                matched.push(part);
+            }
+
+            if ratio == 1.0 {
+                open.clear();
+            }
+            remaining = amount - total;
+        } else {
+            while remaining > dust {
+                let Some(index) = self.method.next_lot(open) else {
+                    break;
+                };
+
+                if open[index].amount <= remaining + dust {
+                    let lot: Lot = open.remove(index);
+                    remaining -= lot.amount;
+                    matched.push(lot);
+                } else {
+                    let lot: &mut Lot = &mut open[index];
+                    let cost: f64 = lot.unit_cost() * remaining;
+                    matched.push(Lot {
+                        amount: remaining,
+                        cost,
+                        ..lot.clone()
+                    });
+                    lot.amount -= remaining;
+                    lot.cost -= cost;
+                    remaining = 0.0;
+                }
+            }
+        }
+
+        if remaining <= dust {
+            remaining = 0.0;
+        }
+
+        let cost: f64 = matched.iter().map(|lot| lot.cost).sum();
+
+        Disposal {
+            transaction_id: transaction.id.clone(),
+            currency: transaction.amount.currency.clone(),
+            disposed_at: transaction.created_at,
+            amount,
+            proceeds,
+            cost,
+            gain: proceeds - cost,
+            uncovered: remaining,
+            lots: matched,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn transaction(
+        id: &str,
+        r#type: &str,
+        amount: &str,
+        currency: &str,
+        native_amount: &str,
+        day: u32,
+    ) -> Transaction {
+        serde_json::from_value(json!({
+            "id": id,
+            "type": r#type,
+            "status": "completed",
+            "amount": {"amount": amount, "currency": currency},
+            "native_amount": {"amount": native_amount, "currency": "USD"},
+            "description": null,
+            "created_at": format!("2024-01-{day:02}T00:00:00Z"),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_cost_basis() {
+        let transactions = vec![
+            transaction("sell", "sell", "-1.5", "BTC", "-600.00", 5),
+            transaction("buy-1", "buy", "1.0", "BTC", "-100.00", 1),
+            transaction("buy-2", "buy", "1.0", "BTC", "-300.00", 2),
+            transaction("buy-3", "buy", "1.0", "BTC", "-200.00", 3),
+            // Moves between own accounts
+            transaction("transfer-out", "transfer", "-1.0", "BTC", "-500.00", 4),
+            transaction("transfer-in", "transfer", "1.0", "BTC", "500.00", 4),
+            transaction("deposit", "fiat_deposit", "1000.00", "USD", "1000.00", 1),
+            // Received out of the history
+            transaction("send", "send", "-1.0", "ETH", "-50.00", 6),
+        ];
+
+        let report: CostBasisReport = CostBasisCalculator::default()
+            .compute(&transactions)
+            .unwrap();
+        assert_eq!(report.method, CostBasisMethod::Fifo);
+        assert_eq!(report.currency.as_deref(), Some("USD"));
+        assert_eq!(report.disposals.len(), 2);
+
+        let sell: &Disposal = &report.disposals[0];
+        let ids: Vec<&str> = sell
+            .lots
+            .iter()
+            .map(|l| l.transaction_id.as_str())
+            .collect();
+        assert_eq!(ids, ["buy-1", "buy-2"]);
+        assert_eq!(sell.cost, 250.0);
+        assert_eq!(sell.gain, 350.0);
+        assert_eq!(sell.uncovered, 0.0);
+
+        let send: &Disposal = &report.disposals[1];
+        assert_eq!(send.cost, 0.0);
+        assert_eq!(send.uncovered, 1.0);
+        assert_eq!(report.realized_gain, 400.0);
+
+        let open: Vec<(&str, f64, f64)> = report
+            .open_lots
+            .iter()
+            .map(|l| (l.transaction_id.as_str(), l.amount, l.cost))
+            .collect();
+        assert_eq!(open, [("buy-2", 0.5, 150.0), ("buy-3", 1.0, 200.0)]);
+
+        // Other methods
+        let cost = |method: CostBasisMethod| {
+            CostBasisCalculator::default()
+                .method(method)
+                .compute(&transactions)
+                .unwrap()
+                .disposals[0]
+                .cost
+        };
+        assert_eq!(cost(CostBasisMethod::Lifo), 350.0);
+        assert_eq!(cost(CostBasisMethod::Hifo), 400.0);
+        assert_eq!(cost(CostBasisMethod::Average), 300.0);
+
+        assert_eq!(
+            "hifo".parse::<CostBasisMethod>().unwrap(),
+            CostBasisMethod::Hifo
+        );
+        assert!("unknown".parse::<CostBasisMethod>().is_err());
+
+        // Sends as transfers
+        let report: CostBasisReport = CostBasisCalculator::default()
+            .external_transfers(true)
+            .compute(&transactions)
+            .unwrap();
+        assert_eq!(report.disposals.len(), 1);
+        assert_eq!(report.realized_gain, 350.0);
+
+        // Mixed native currencies
+        let mut eur: Transaction = transaction("eur", "buy", "1.0", "BTC", "-90.00", 7);
+        eur.native_amount.currency = String::from("EUR");
+        assert!(
+            CostBasisCalculator::default()
+                .compute(transactions.iter().chain([&eur]))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_float_dust() {
+        // 0.1 + 0.2 != 0.3 in floating point
+        let transactions = vec![
+            transaction("buy-1", "buy", "0.1", "BTC", "-10.00", 1),
+            transaction("buy-2", "buy", "0.2", "BTC", "-30.00", 2),
+            transaction("sell", "sell", "-0.3", "BTC", "-60.00", 3),
+        ];
+
+        for method in [
+            CostBasisMethod::Fifo,
+            CostBasisMethod::Lifo,
+            CostBasisMethod::Hifo,
+            CostBasisMethod::Average,
+        ] {
+            let report: CostBasisReport = CostBasisCalculator::default()
+                .method(method)
+                .compute(&transactions)
+                .unwrap();
+
+            assert!(report.open_lots.is_empty(), "{method}: phantom lots");
+            let sell: &Disposal = &report.disposals[0];
+            assert_eq!(sell.uncovered, 0.0, "{method}: oversell");
+            assert_eq!(sell.lots.len(), 2);
+            assert!((sell.cost - 40.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_average_without_amount() {
+        let calculator = CostBasisCalculator::default().method(CostBasisMethod::Average);
+        let sell: Transaction = transaction("sell", "sell", "-1.0", "BTC", "-100.00", 2);
+
+        let mut open: Vec<Lot> = vec![Lot {
+            transaction_id: String::from("buy"),
+            currency: String::from("BTC"),
+            acquired_at: sell.created_at,
+            amount: 0.0,
+            cost: 0.0,
+        }];
+
+        let disposal: Disposal = calculator.dispose(&sell, &mut open);
+        assert!(disposal.lots.is_empty());
+        assert_eq!(disposal.uncovered, 1.0);
+        assert_eq!(disposal.gain, 100.0);
+    }
+}
@@ -1,21 +1,104 @@
 //! Coinbase App error
 
+use std::fmt;
+use std::time::Duration;
+
+use http::{Method, StatusCode};
 use thiserror::Error;
 
 use super::response::CoinbaseErrorMessage;
+use super::transport::HttpResponse;
+
+/// Max length of the body snippets, in bytes
+const BODY_SNIPPET_LEN: usize = 512;
+
+/// Coinbase error IDs of the authentication failures
+const AUTH_ERROR_IDS: [&str; 5] = [
+    "authentication_error",
+    "invalid_token",
+    "expired_token",
+    "revoked_token",
+    "invalid_scope",
+];
+
+/// Request context of an error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorContext {
+    /// HTTP method
+    pub method: Method,
+    /// Path, without the query
+    pub path: String,
+    /// HTTP status code, if a response has been received
+    pub status: Option<u16>,
+    /// Request ID, assigned by Coinbase
+    pub request_id: Option<String>,
+    /// Delay of the `Retry-After` header
+    pub retry_after: Option<Duration>,
+    /// Beginning of the response body
+    pub body: Option<String>,
+}
+
+impl ErrorContext {
+    pub(crate) fn new(method: Method, path: &str, response: Option<&HttpResponse>) -> Self {
+        Self {
+            method,
+            path: path.split('?').next().unwrap_or_default().to_string(),
+            status: response.map(|r| r.status.as_u16()),
+            request_id: response.and_then(|r| r.request_id()).map(String::from),
+            retry_after: response.and_then(|r| r.retry_after()),
+            body: response
+                .filter(|r| !r.body.is_empty())
+                .map(|r| snippet(&r.body)),
+        }
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.method, self.path)?;
+
+        if let Some(request_id) = &self.request_id {
+            write!(f, " (request ID {request_id})")?;
+        }
+
+        Ok(())
+    }
+}
 
 /// Coinbase App error
 #[derive(Debug, Error)]
 pub enum Error {
     /// Reqwest error
+    #[cfg(feature = "reqwest")]
     #[error(transparent)]
     Reqwest(#[from] reqwest::Error),
+    /// HTTP transport error
+    #[error("transport: {0}")]
+    Transport(String),
+    /// HTTP error status, without Coinbase error message
+    #[error("HTTP status {0}")]
+    HttpStatus(u16),
+    /// I/O error
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// Environment variable error
+    #[error("environment variable {0}: {1}")]
+    EnvVar(String, #[source] std::env::VarError),
     /// Url error
     #[error(transparent)]
     Url(#[from] url::ParseError),
     /// JSON error
     #[error(transparent)]
     Json(#[from] serde_json::Error),
+    /// JSON decoding error, with the path of the failing field (i.e., `data[3].amount.amount`)
+    #[error("JSON at `{path}`: {source}")]
+    Decode {
+        /// Path of the failing field
+        path: String,
+        /// JSON error
+        #[source]
+        source: serde_json::Error,
+    },
     /// Coinbase response error
     #[error("coinbase: {0}")]
     Coinbase(CoinbaseErrorMessage),
@@ -25,7 +108,191 @@ pub enum Error {
     /// Bad signature
     #[error("bad signature: {0}")]
     BadSignature(String),
+    /// OAuth2 error
+    #[error("oauth2: {0}")]
+    OAuth2(String),
+    /// CSV error
+    #[cfg(feature = "export")]
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    /// Export error
+    #[error("export: {0}")]
+    Export(String),
+    /// Cost basis error
+    #[error("cost basis: {0}")]
+    CostBasis(String),
+    /// Cassette error
+    #[error("cassette: {0}")]
+    Cassette(String),
+    /// Invalid JWT lifetime
+    #[error("invalid JWT lifetime: {0:?}")]
+    InvalidJwtLifetime(std::time::Duration),
     /// Host not found
     #[error("host not found")]
     HostNotFound,
+    /// Error of a request, with its context
+    ///
+    /// The error is also exposed as the [source](std::error::Error::source).
+    #[error("{context}: {source}")]
+    Request {
+        /// Request context
+        context: Box<ErrorContext>,
+        /// Error
+        #[source]
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Attach the request context
+    pub(crate) fn with_context(self, context: ErrorContext) -> Self {
+        match self {
+            // Keep the original context
+            Self::Request { .. } => self,
+            error => Self::Request {
+                context: Box::new(context),
+                source: Box::new(error),
+            },
+        }
+    }
+
+    /// Request context, if any
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            Self::Request { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Error without the request context
+    pub fn inner(&self) -> &Self {
+        match self {
+            Self::Request { source, .. } => source.inner(),
+            error => error,
+        }
+    }
+
+    /// HTTP status code, if a response has been received
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            Self::Request { context, source } => context.status.or_else(|| source.status()),
+            Self::HttpStatus(status) => Some(*status),
+            #[cfg(feature = "reqwest")]
+            Self::Reqwest(e) => e.status().map(|s| s.as_u16()),
+            _ => None,
+        }
+    }
+
+    /// Check if the request may succeed if retried (rate limit, server error, transport error)
+    pub fn is_retryable(&self) -> bool {
+        if let Some(status) = self.status().and_then(|s| StatusCode::from_u16(s).ok()) {
+            return status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error();
+        }
+
+        match self.inner() {
+            Self::Transport(..) => true,
+            #[cfg(feature = "reqwest")]
+            Self::Reqwest(e) => e.is_timeout() || e.is_connect(),
+            _ => false,
+        }
+    }
+
+    /// Check if it's an authentication or authorization failure
+    pub fn is_auth(&self) -> bool {
+        if matches!(self.status(), Some(401 | 403)) {
+            return true;
+        }
+
+        match self.inner() {
+            Self::Coinbase(e) => AUTH_ERROR_IDS.contains(&e.id.as_str()),
+            Self::OAuth2(..) | Self::InvalidPrivateKey(..) => true,
+            _ => false,
+        }
+    }
+
+    /// Check if the request has been rate-limited
+    pub fn is_rate_limited(&self) -> bool {
+        match self.inner() {
+            Self::Coinbase(e) if e.id == "rate_limit_exceeded" => true,
+            _ => self.status() == Some(429),
+        }
+    }
+
+    /// Delay of the `Retry-After` header, if any
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.context()?.retry_after
+    }
+}
+
+impl From<serde_path_to_error::Error<serde_json::Error>> for Error {
+    fn from(e: serde_path_to_error::Error<serde_json::Error>) -> Self {
+        Self::Decode {
+            path: e.path().to_string(),
+            source: e.into_inner(),
+        }
+    }
+}
+
+/// Beginning of the body, cut at a char boundary.
+fn snippet(body: &[u8]) -> String {
+    let body: String = String::from_utf8_lossy(body).into_owned();
+
+    if body.len() <= BODY_SNIPPET_LEN {
+        return body;
+    }
+
+    let mut end: usize = BODY_SNIPPET_LEN;
+    while !body.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    format!("{}...", &body[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderMap;
+
+    use super::*;
+
+    #[test]
+    fn test_error_context() {
+        let mut headers = HeaderMap::new();
+        headers.insert("cb-request-id", "abc".parse().unwrap());
+        headers.insert("retry-after", "5".parse().unwrap());
+        let response = HttpResponse {
+            status: StatusCode::TOO_MANY_REQUESTS,
+            headers,
+            body: "é".repeat(BODY_SNIPPET_LEN).into_bytes(),
+        };
+
+        let error = Error::HttpStatus(429).with_context(ErrorContext::new(
+            Method::GET,
+            "/v2/accounts?limit=100",
+            Some(&response),
+        ));
+        assert_eq!(
+            error.to_string(),
+            "GET /v2/accounts (request ID abc): HTTP status 429"
+        );
+        assert_eq!(
+            std::error::Error::source(&error).unwrap().to_string(),
+            "HTTP status 429"
+        );
+        assert!(matches!(error.inner(), Error::HttpStatus(429)));
+        assert!(error.is_retryable());
+        assert!(error.is_rate_limited());
+        assert!(!error.is_auth());
+        assert_eq!(error.retry_after(), Some(Duration::from_secs(5)));
+
+        let body: &str = error.context().unwrap().body.as_deref().unwrap();
+        assert!(body.ends_with("...") && body.len() <= BODY_SNIPPET_LEN + 3);
+
+        let error = Error::Coinbase(CoinbaseErrorMessage {
+            id: String::from("expired_token"),
+            message: String::from("Token expired"),
+        });
+        assert!(error.is_auth());
+        assert!(!error.is_retryable());
+    }
 }
@@ -0,0 +1,510 @@
+//! CSV and JSON Lines export
+//!
+//! The transactions are written in a stable order (by creation time, then ID),
+//! with configurable columns and timezone.
+
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+
+use chrono::{DateTime, FixedOffset, Local, SecondsFormat, Utc};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+use super::error::Error;
+use super::response::{Account, Transaction};
+
+/// Header of the accounts export
+const ACCOUNT_COLUMNS: [&str; 5] = ["id", "name", "type", "currency", "balance"];
+
+/// Export format
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ExportFormat {
+    /// CSV, with a header row
+    #[default]
+    Csv,
+    /// JSON Lines (one JSON object per line)
+    Ndjson,
+}
+
+impl FromStr for ExportFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "ndjson" | "jsonl" => Ok(Self::Ndjson),
+            _ => Err(Error::Export(format!("unknown format: {s}"))),
+        }
+    }
+}
+
+/// Transaction column
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Column {
+    /// Transaction ID
+    Id,
+    /// Transaction type (i.e., `buy`)
+    Type,
+    /// Transaction status (i.e., `completed`)
+    Status,
+    /// Amount
+    Amount,
+    /// Currency of the amount
+    Currency,
+    /// Amount in the user's native currency
+    NativeAmount,
+    /// Native currency
+    NativeCurrency,
+    /// Creation time (RFC 3339), in the export timezone
+    CreatedAt,
+    /// Description
+    Description,
+    /// On-chain transaction hash, for the network transactions
+    NetworkHash,
+}
+
+impl Column {
+    /// All the columns
+    pub const ALL: [Self; 10] = [
+        Self::Id,
+        Self::Type,
+        Self::Status,
+        Self::Amount,
+        Self::Currency,
+        Self::NativeAmount,
+        Self::NativeCurrency,
+        Self::CreatedAt,
+        Self::Description,
+        Self::NetworkHash,
+    ];
+
+    /// Name, used as header or JSON key
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Id => "id",
+            Self::Type => "type",
+            Self::Status => "status",
+            Self::Amount => "amount",
+            Self::Currency => "currency",
+            Self::NativeAmount => "native_amount",
+            Self::NativeCurrency => "native_currency",
+            Self::CreatedAt => "created_at",
+            Self::Description => "description",
+            Self::NetworkHash => "network_hash",
+        }
+    }
+}
+
+impl fmt::Display for Column {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Column {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|column| column.name() == s)
+            .ok_or_else(|| Error::Export(format!("unknown column: {s}")))
+    }
+}
+
+/// Timezone of the exported times
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ExportTimezone {
+    /// UTC
+    #[default]
+    Utc,
+    /// Timezone of the system
+    Local,
+    /// Fixed offset (i.e., `+02:00`)
+    Fixed(FixedOffset),
+}
+
+impl FromStr for ExportTimezone {
+    type Err = Error;
+
+    /// Parse `utc`, `local` or a fixed offset (i.e., `+02:00`)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "utc" => Ok(Self::Utc),
+            "local" => Ok(Self::Local),
+            offset if offset.starts_with(['+', '-']) => offset
+                .parse()
+                .map(Self::Fixed)
+                .map_err(|e| Error::Export(format!("invalid offset {offset}: {e}"))),
+            _ => Err(Error::Export(format!("unknown timezone: {s}"))),
+        }
+    }
+}
+
+impl ExportTimezone {
+    fn format(&self, time: &DateTime<Utc>) -> String {
+        match self {
+            Self::Utc => time.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+            Self::Local => time
+                .with_timezone(&Local)
+                .to_rfc3339_opts(SecondsFormat::AutoSi, false),
+            Self::Fixed(offset) => time
+                .with_timezone(offset)
+                .to_rfc3339_opts(SecondsFormat::AutoSi, false),
+        }
+    }
+}
+
+/// Transaction exporter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionExporter {
+    /// Columns, in this order (default: all)
+    pub columns: Vec<Column>,
+    /// Timezone of the creation times (default: UTC)
+    pub timezone: ExportTimezone,
+}
+
+impl Default for TransactionExporter {
+    fn default() -> Self {
+        Self {
+            columns: Column::ALL.to_vec(),
+            timezone: ExportTimezone::default(),
+        }
+    }
+}
+
+impl TransactionExporter {
+    /// Set columns
+    #[inline]
+    pub fn columns(mut self, columns: Vec<Column>) -> Self {
+        self.columns = columns;
+        self
+    }
+
+    /// Set timezone
+    #[inline]
+    pub fn timezone(mut self, timezone: ExportTimezone) -> Self {
+        self.timezone = timezone;
+        self
+    }
+
+    /// Write the transactions in a format
+    pub fn write<'a, W, I>(
+        &self,
+        format: ExportFormat,
+        writer: W,
+        transactions: I,
+    ) -> Result<(), Error>
+    where
+        W: Write,
+        I: IntoIterator<Item = &'a Transaction>,
+    {
+        match format {
+            ExportFormat::Csv => self.write_csv(writer, transactions),
+            ExportFormat::Ndjson => self.write_ndjson(writer, transactions),
+        }
+    }
+
+    /// Write the transactions as CSV
+    pub fn write_csv<'a, W, I>(&self, writer: W, transactions: I) -> Result<(), Error>
+    where
+        W: Write,
+        I: IntoIterator<Item = &'a Transaction>,
+    {
+        let mut writer = csv::Writer::from_writer(writer);
+
+        writer.write_record(self.columns.iter().map(Column::name))?;
+
+        for transaction in sorted(transactions) {
+            writer.write_record(self.columns.iter().map(|c| self.value(transaction, *c)))?;
+        }
+
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Write the transactions as JSON Lines
+    pub fn write_ndjson<'a, W, I>(&self, mut writer: W, transactions: I) -> Result<(), Error>
+    where
+        W: Write,
+        I: IntoIterator<Item = &'a Transaction>,
+    {
+        for transaction in sorted(transactions) {
+            serde_json::to_writer(
+                &mut writer,
+                &Row {
+                    exporter: self,
+                    transaction,
+                },
+            )?;
+            writer.write_all(b"\n")?;
+        }
+
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    fn value(&self, transaction: &Transaction, column: Column) -> String {
+        match column {
+            Column::Id => transaction.id.clone(),
+            Column::Type => enum_name(&transaction.r#type),
+            Column::Status => enum_name(&transaction.status),
+            Column::Amount => transaction.amount.amount.to_string(),
+            Column::Currency => transaction.amount.currency.clone(),
+            Column::NativeAmount => transaction.native_amount.amount.to_string(),
+            Column::NativeCurrency => transaction.native_amount.currency.clone(),
+            Column::CreatedAt => self.timezone.format(&transaction.created_at),
+            Column::Description => transaction.description.clone().unwrap_or_default(),
+            Column::NetworkHash => transaction
+                .extra
+                .get("network")
+                .and_then(|network| network.get("hash"))
+                .and_then(|hash| hash.as_str())
+                .unwrap_or_default()
+                .to_string(),
+        }
+    }
+}
+
+/// Write the accounts in a format (`id`, `name`, `type`, `currency` and `balance` columns)
+pub fn write_accounts<W>(format: ExportFormat, writer: W, accounts: &[Account]) -> Result<(), Error>
+where
+    W: Write,
+{
+    match format {
+        ExportFormat::Csv => write_accounts_csv(writer, accounts),
+        ExportFormat::Ndjson => write_accounts_ndjson(writer, accounts),
+    }
+}
+
+/// Write the accounts as CSV (`id`, `name`, `type`, `currency` and `balance` columns)
+pub fn write_accounts_csv<W>(writer: W, accounts: &[Account]) -> Result<(), Error>
+where
+    W: Write,
+{
+    let mut writer = csv::Writer::from_writer(writer);
+
+    writer.write_record(ACCOUNT_COLUMNS)?;
+
+    for account in sorted_accounts(accounts) {
+        writer.write_record(account_values(account))?;
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Write the accounts as JSON Lines, with the keys of the CSV columns
+pub fn write_accounts_ndjson<W>(mut writer: W, accounts: &[Account]) -> Result<(), Error>
+where
+    W: Write,
+{
+    for account in sorted_accounts(accounts) {
+        serde_json::to_writer(&mut writer, &AccountRow(account))?;
+        writer.write_all(b"\n")?;
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Sorts the accounts by ID.
+fn sorted_accounts(accounts: &[Account]) -> Vec<&Account> {
+    let mut accounts: Vec<&Account> = accounts.iter().collect();
+    accounts.sort_by(|a, b| a.id.cmp(&b.id));
+    accounts
+}
+
+/// Values of the [`ACCOUNT_COLUMNS`]
+fn account_values(account: &Account) -> [String; 5] {
+    [
+        account.id.clone(),
+        account.name.clone(),
+        account.r#type.clone(),
+        account.balance.currency.clone(),
+        account.balance.amount.to_string(),
+    ]
+}
+
+/// Row of the JSON Lines export, with the keys in the columns order
+struct Row<'a> {
+    exporter: &'a TransactionExporter,
+    transaction: &'a Transaction,
+}
+
+impl Serialize for Row<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(self.exporter.columns.len()))?;
+        for column in self.exporter.columns.iter() {
+            map.serialize_entry(
+                column.name(),
+                &self.exporter.value(self.transaction, *column),
+            )?;
+        }
+        map.end()
+    }
+}
+
+/// Row of the accounts JSON Lines export, with the keys in the columns order
+struct AccountRow<'a>(&'a Account);
+
+impl Serialize for AccountRow<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(ACCOUNT_COLUMNS.len()))?;
+        for (key, value) in ACCOUNT_COLUMNS.into_iter().zip(account_values(self.0)) {
+            map.serialize_entry(key, &value)?;
+        }
+        map.end()
+    }
+}
+
+/// Sorts the transactions by creation time, then ID.
+fn sorted<'a, I>(transactions: I) -> Vec<&'a Transaction>
+where
+    I: IntoIterator<Item = &'a Transaction>,
+{
+    let mut transactions: Vec<&Transaction> = transactions.into_iter().collect();
+    transactions.sort_by(|a, b| {
+        a.created_at
+            .cmp(&b.created_at)
+            .then_with(|| a.id.cmp(&b.id))
+    });
+    transactions
+}
+
+/// Wire name of an enum variant (i.e., `buy`).
+fn enum_name<T>(value: &T) -> String
+where
+    T: Serialize,
+{
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    fn transaction(id: &str, created_at: &str) -> Transaction {
+        serde_json::from_value(json!({
+            "id": id,
+            "type": "send",
+            "status": "completed",
+            "amount": {"amount": "-0.001", "currency": "BTC"},
+            "native_amount": {"amount": "-10.50", "currency": "USD"},
+            "description": "Rent, March",
+            "created_at": created_at,
+            "network": {"status": "confirmed", "hash": "0xabc"},
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_export() {
+        let transactions = vec![
+            transaction("tx-2", "2024-03-01T12:00:00Z"),
+            transaction("tx-1", "2024-03-01T12:00:00Z"),
+            transaction("tx-0", "2024-02-01T23:30:00Z"),
+        ];
+
+        let exporter = TransactionExporter::default()
+            .columns(vec![
+                Column::Id,
+                Column::Type,
+                Column::Amount,
+                Column::CreatedAt,
+                Column::Description,
+                Column::NetworkHash,
+            ])
+            .timezone(ExportTimezone::Fixed(
+                FixedOffset::east_opt(2 * 3600).unwrap(),
+            ));
+
+        let mut csv: Vec<u8> = Vec::new();
+        exporter.write_csv(&mut csv, &transactions).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "id,type,amount,created_at,description,network_hash\n\
+             tx-0,send,-0.001,2024-02-02T01:30:00+02:00,\"Rent, March\",0xabc\n\
+             tx-1,send,-0.001,2024-03-01T14:00:00+02:00,\"Rent, March\",0xabc\n\
+             tx-2,send,-0.001,2024-03-01T14:00:00+02:00,\"Rent, March\",0xabc\n"
+        );
+
+        let mut ndjson: Vec<u8> = Vec::new();
+        exporter
+            .write(ExportFormat::Ndjson, &mut ndjson, &transactions)
+            .unwrap();
+        let ndjson: String = String::from_utf8(ndjson).unwrap();
+        let lines: Vec<&str> = ndjson.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with(r#"{"id":"tx-0","type":"send""#));
+        let row: Value = serde_json::from_str(lines[2]).unwrap();
+        assert_eq!(row["created_at"], "2024-03-01T14:00:00+02:00");
+
+        assert_eq!(
+            "native_amount".parse::<Column>().unwrap(),
+            Column::NativeAmount
+        );
+        assert!("unknown".parse::<Column>().is_err());
+    }
+
+    #[test]
+    fn test_export_accounts() {
+        let account: Account = serde_json::from_value(json!({
+            "id": "btc",
+            "name": "BTC Wallet, main",
+            "primary": true,
+            "type": "wallet",
+            "currency": {"asset_id": "BTC", "code": "BTC", "name": "Bitcoin"},
+            "balance": {"amount": "0.5", "currency": "BTC"},
+        }))
+        .unwrap();
+
+        let mut csv: Vec<u8> = Vec::new();
+        write_accounts_csv(&mut csv, std::slice::from_ref(&account)).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "id,name,type,currency,balance\n\
+             btc,\"BTC Wallet, main\",wallet,BTC,0.5\n"
+        );
+
+        let mut ndjson: Vec<u8> = Vec::new();
+        write_accounts(ExportFormat::Ndjson, &mut ndjson, &[account]).unwrap();
+        assert_eq!(
+            String::from_utf8(ndjson).unwrap(),
+            "{\"id\":\"btc\",\"name\":\"BTC Wallet, main\",\"type\":\"wallet\",\"currency\":\"BTC\",\"balance\":\"0.5\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_timezone() {
+        assert_eq!(
+            "utc".parse::<ExportTimezone>().unwrap(),
+            ExportTimezone::Utc
+        );
+        assert_eq!(
+            "local".parse::<ExportTimezone>().unwrap(),
+            ExportTimezone::Local
+        );
+        assert_eq!(
+            "-05:30".parse::<ExportTimezone>().unwrap(),
+            ExportTimezone::Fixed(FixedOffset::west_opt(5 * 3600 + 30 * 60).unwrap())
+        );
+        assert!("+25:00".parse::<ExportTimezone>().is_err());
+        assert!("Europe/Paris".parse::<ExportTimezone>().is_err());
+    }
+}
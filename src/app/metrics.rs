@@ -0,0 +1,398 @@
+//! API usage metrics
+//!
+//! The [`MetricsRecorder`] hook receives the measurements of the client, labeled by endpoint
+//! template (i.e., `/v2/accounts/:id/transactions`) to keep a low cardinality.
+//! [`OpenTelemetryRecorder`] exports them as OpenTelemetry instruments (`opentelemetry` feature).
+
+use std::fmt;
+use std::time::Duration;
+
+use http::StatusCode;
+
+/// Placeholder of the ID segments in the endpoint templates
+const ID_PLACEHOLDER: &str = ":id";
+
+/// Status class of a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StatusClass {
+    /// `1xx`
+    Informational,
+    /// `2xx`
+    Success,
+    /// `3xx`
+    Redirection,
+    /// `4xx`
+    ClientError,
+    /// `5xx`
+    ServerError,
+    /// No response (i.e., connection error or timeout)
+    TransportError,
+}
+
+impl StatusClass {
+    /// Label (i.e., `2xx`)
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Informational => "1xx",
+            Self::Success => "2xx",
+            Self::Redirection => "3xx",
+            Self::ClientError => "4xx",
+            Self::ServerError => "5xx",
+            Self::TransportError => "error",
+        }
+    }
+}
+
+impl From<StatusCode> for StatusClass {
+    fn from(status: StatusCode) -> Self {
+        if status.is_informational() {
+            Self::Informational
+        } else if status.is_success() {
+            Self::Success
+        } else if status.is_redirection() {
+            Self::Redirection
+        } else if status.is_client_error() {
+            Self::ClientError
+        } else {
+            Self::ServerError
+        }
+    }
+}
+
+impl fmt::Display for StatusClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Metrics recorder
+///
+/// All the methods have a no-op default implementation.
+pub trait MetricsRecorder: fmt::Debug + Send + Sync {
+    /// Called after every attempt of a request
+    fn record_request(&self, endpoint: &str, status: StatusClass, latency: Duration) {
+        let _ = (endpoint, status, latency);
+    }
+
+    /// Called before retrying a server error
+    fn record_retry(&self, endpoint: &str) {
+        let _ = endpoint;
+    }
+
+    /// Called before waiting for the rate limit
+    fn record_rate_limit_wait(&self, endpoint: &str, delay: Duration) {
+        let _ = (endpoint, delay);
+    }
+
+    /// Called after signing a JWT (not for the cached tokens)
+    fn record_jwt_signing(&self, duration: Duration) {
+        let _ = duration;
+    }
+
+    /// Called after fetching all the pages of a list endpoint
+    fn record_pagination(&self, endpoint: &str, pages: usize) {
+        let _ = (endpoint, pages);
+    }
+}
+
+/// Endpoint template of a path, with the IDs replaced by `:id`
+///
+/// The v2 paths alternate collections and IDs (i.e., `/v2/accounts/<id>/transactions/<id>`).
+pub fn endpoint_template(path: &str) -> String {
+    let path: &str = path.split('?').next().unwrap_or_default();
+
+    let mut template: String = String::with_capacity(path.len());
+
+    for (index, segment) in path.trim_matches('/').split('/').enumerate() {
+        template.push('/');
+
+        // Index 0 is the version, then the collections are at the odd indexes
+        if index > 0 && index % 2 == 0 && !segment.is_empty() {
+            template.push_str(ID_PLACEHOLDER);
+        } else {
+            template.push_str(segment);
+        }
+    }
+
+    template
+}
+
+/// [OpenTelemetry](https://opentelemetry.io) recorder
+///
+/// Instruments:
+/// - `coinbase.requests` (counter, by `endpoint` and `status_class`)
+/// - `coinbase.request.duration` (histogram, secs, by `endpoint` and `status_class`)
+/// - `coinbase.retries` (counter, by `endpoint`)
+/// - `coinbase.rate_limit.waits` (counter, by `endpoint`)
+/// - `coinbase.rate_limit.wait.duration` (histogram, secs, by `endpoint`)
+/// - `coinbase.jwt.signing.duration` (histogram, secs)
+/// - `coinbase.pagination.pages` (histogram, by `endpoint`)
+#[cfg(feature = "opentelemetry")]
+#[derive(Debug, Clone)]
+pub struct OpenTelemetryRecorder {
+    requests: opentelemetry::metrics::Counter<u64>,
+    request_duration: opentelemetry::metrics::Histogram<f64>,
+    retries: opentelemetry::metrics::Counter<u64>,
+    rate_limit_waits: opentelemetry::metrics::Counter<u64>,
+    rate_limit_wait_duration: opentelemetry::metrics::Histogram<f64>,
+    jwt_signing_duration: opentelemetry::metrics::Histogram<f64>,
+    pagination_pages: opentelemetry::metrics::Histogram<u64>,
+}
+
+#[cfg(feature = "opentelemetry")]
+impl OpenTelemetryRecorder {
+    /// New recorder, creating the instruments with the meter
+    pub fn new(meter: &opentelemetry::metrics::Meter) -> Self {
+        Self {
+            requests: meter
+                .u64_counter("coinbase.requests")
+                .with_description("Requests sent to the Coinbase API")
+                .build(),
+            request_duration: meter
+                .f64_histogram("coinbase.request.duration")
+                .with_description("Latency of the requests")
+                .with_unit("s")
+                .build(),
+            retries: meter
+                .u64_counter("coinbase.retries")
+                .with_description("Retries of the server errors")
+                .build(),
+            rate_limit_waits: meter
+                .u64_counter("coinbase.rate_limit.waits")
+                .with_description("Waits for the rate limit")
+                .build(),
+            rate_limit_wait_duration: meter
+                .f64_histogram("coinbase.rate_limit.wait.duration")
+                .with_description("Time spent waiting for the rate limit")
+                .with_unit("s")
+                .build(),
+            jwt_signing_duration: meter
+                .f64_histogram("coinbase.jwt.signing.duration")
+                .with_description("Time spent signing the JWTs")
+                .with_unit("s")
+                .build(),
+            pagination_pages: meter
+                .u64_histogram("coinbase.pagination.pages")
+                .with_description("Pages fetched by the list endpoints")
+                .build(),
+        }
+    }
+}
+
+#[cfg(feature = "opentelemetry")]
+impl MetricsRecorder for OpenTelemetryRecorder {
+    fn record_request(&self, endpoint: &str, status: StatusClass, latency: Duration) {
+        let attributes = [
+            opentelemetry::KeyValue::new("endpoint", endpoint.to_string()),
+            opentelemetry::KeyValue::new("status_class", status.as_str()),
+        ];
+        self.requests.add(1, &attributes);
+        self.request_duration
+            .record(latency.as_secs_f64(), &attributes);
+    }
+
+    fn record_retry(&self, endpoint: &str) {
+        let attributes = [opentelemetry::KeyValue::new(
+            "endpoint",
+            endpoint.to_string(),
+        )];
+        self.retries.add(1, &attributes);
+    }
+
+    fn record_rate_limit_wait(&self, endpoint: &str, delay: Duration) {
+        let attributes = [opentelemetry::KeyValue::new(
+            "endpoint",
+            endpoint.to_string(),
+        )];
+        self.rate_limit_waits.add(1, &attributes);
+        self.rate_limit_wait_duration
+            .record(delay.as_secs_f64(), &attributes);
+    }
+
+    fn record_jwt_signing(&self, duration: Duration) {
+        self.jwt_signing_duration
+            .record(duration.as_secs_f64(), &[]);
+    }
+
+    fn record_pagination(&self, endpoint: &str, pages: usize) {
+        let attributes = [opentelemetry::KeyValue::new(
+            "endpoint",
+            endpoint.to_string(),
+        )];
+        self.pagination_pages.record(pages as u64, &attributes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint_template() {
+        assert_eq!(endpoint_template("/v2/accounts"), "/v2/accounts");
+        assert_eq!(endpoint_template("/v2/accounts/btc"), "/v2/accounts/:id");
+        assert_eq!(
+            endpoint_template("/v2/accounts/2bbf394c/transactions?limit=100"),
+            "/v2/accounts/:id/transactions"
+        );
+        assert_eq!(
+            endpoint_template("/v2/accounts/2bbf394c/transactions/57ffb4ae"),
+            "/v2/accounts/:id/transactions/:id"
+        );
+        assert_eq!(
+            endpoint_template("/v2/exchange-rates"),
+            "/v2/exchange-rates"
+        );
+    }
+
+    #[test]
+    fn test_status_class() {
+        assert_eq!(StatusClass::from(StatusCode::OK).as_str(), "2xx");
+        assert_eq!(
+            StatusClass::from(StatusCode::TOO_MANY_REQUESTS),
+            StatusClass::ClientError
+        );
+        assert_eq!(
+            StatusClass::from(StatusCode::BAD_GATEWAY),
+            StatusClass::ServerError
+        );
+    }
+
+    #[cfg(feature = "opentelemetry")]
+    #[test]
+    fn test_opentelemetry_recorder() {
+        use std::sync::{Arc, Mutex};
+
+        use opentelemetry::KeyValue;
+        use opentelemetry::metrics::{
+            Counter, Histogram, HistogramBuilder, InstrumentBuilder, InstrumentProvider, Meter,
+            SyncInstrument,
+        };
+
+        /// Measurement: instrument name, value and attributes
+        type Measurement = (String, f64, Vec<(String, String)>);
+
+        /// In-memory instruments, keeping all the measurements
+        #[derive(Default)]
+        struct MemoryProvider {
+            measurements: Arc<Mutex<Vec<Measurement>>>,
+        }
+
+        struct MemoryInstrument {
+            name: String,
+            measurements: Arc<Mutex<Vec<Measurement>>>,
+        }
+
+        impl MemoryInstrument {
+            fn push(&self, value: f64, attributes: &[KeyValue]) {
+                let attributes = attributes
+                    .iter()
+                    .map(|kv| (kv.key.to_string(), kv.value.to_string()))
+                    .collect();
+                self.measurements
+                    .lock()
+                    .unwrap()
+                    .push((self.name.clone(), value, attributes));
+            }
+        }
+
+        impl SyncInstrument<u64> for MemoryInstrument {
+            fn measure(&self, measurement: u64, attributes: &[KeyValue]) {
+                self.push(measurement as f64, attributes);
+            }
+        }
+
+        impl SyncInstrument<f64> for MemoryInstrument {
+            fn measure(&self, measurement: f64, attributes: &[KeyValue]) {
+                self.push(measurement, attributes);
+            }
+        }
+
+        impl MemoryProvider {
+            fn instrument(&self, name: &str) -> Arc<MemoryInstrument> {
+                Arc::new(MemoryInstrument {
+                    name: name.to_string(),
+                    measurements: self.measurements.clone(),
+                })
+            }
+        }
+
+        impl InstrumentProvider for MemoryProvider {
+            fn u64_counter(&self, builder: InstrumentBuilder<'_, Counter<u64>>) -> Counter<u64> {
+                Counter::new(self.instrument(&builder.name))
+            }
+
+            fn f64_histogram(
+                &self,
+                builder: HistogramBuilder<'_, Histogram<f64>>,
+            ) -> Histogram<f64> {
+                Histogram::new(self.instrument(&builder.name))
+            }
+
+            fn u64_histogram(
+                &self,
+                builder: HistogramBuilder<'_, Histogram<u64>>,
+            ) -> Histogram<u64> {
+                Histogram::new(self.instrument(&builder.name))
+            }
+        }
+
+        let provider = MemoryProvider::default();
+        let measurements = provider.measurements.clone();
+        let meter = Meter::new(Arc::new(provider));
+
+        let recorder = OpenTelemetryRecorder::new(&meter);
+        recorder.record_request(
+            "/v2/accounts",
+            StatusClass::Success,
+            Duration::from_millis(5),
+        );
+        recorder.record_retry("/v2/accounts/:id");
+        recorder.record_rate_limit_wait("/v2/accounts", Duration::from_secs(2));
+        recorder.record_jwt_signing(Duration::from_millis(1));
+        recorder.record_pagination("/v2/accounts", 2);
+
+        let request_attributes = vec![
+            (String::from("endpoint"), String::from("/v2/accounts")),
+            (String::from("status_class"), String::from("2xx")),
+        ];
+        let endpoint = |endpoint: &str| vec![(String::from("endpoint"), endpoint.to_string())];
+
+        assert_eq!(
+            *measurements.lock().unwrap(),
+            [
+                (
+                    String::from("coinbase.requests"),
+                    1.0,
+                    request_attributes.clone()
+                ),
+                (
+                    String::from("coinbase.request.duration"),
+                    0.005,
+                    request_attributes
+                ),
+                (
+                    String::from("coinbase.retries"),
+                    1.0,
+                    endpoint("/v2/accounts/:id")
+                ),
+                (
+                    String::from("coinbase.rate_limit.waits"),
+                    1.0,
+                    endpoint("/v2/accounts")
+                ),
+                (
+                    String::from("coinbase.rate_limit.wait.duration"),
+                    2.0,
+                    endpoint("/v2/accounts")
+                ),
+                (String::from("coinbase.jwt.signing.duration"), 0.001, vec![]),
+                (
+                    String::from("coinbase.pagination.pages"),
+                    2.0,
+                    endpoint("/v2/accounts")
+                ),
+            ]
+        );
+    }
+}
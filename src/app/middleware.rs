@@ -0,0 +1,46 @@
+//! Request/response middlewares
+//!
+//! Hooks around every request (i.e., to add headers, audit the calls or collect metrics).
+
+use std::fmt;
+use std::time::Duration;
+
+use http::Method;
+use url::Url;
+
+use super::error::Error;
+use super::transport::{HttpRequest, HttpResponse};
+
+/// Request context, passed to the hooks after sending
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestContext {
+    /// Method
+    pub method: Method,
+    /// URL
+    pub url: Url,
+    /// Time elapsed since the request has been sent
+    pub elapsed: Duration,
+}
+
+/// Request/response middleware
+///
+/// The hooks are called in the registration order. All of them have a no-op default implementation.
+pub trait Middleware: fmt::Debug + Send + Sync {
+    /// Called before sending the request (i.e., to add headers like `CB-2FA-TOKEN` or correlation IDs)
+    ///
+    /// Returning an error aborts the request.
+    fn before_send(&self, request: &mut HttpRequest) -> Result<(), Error> {
+        let _ = request;
+        Ok(())
+    }
+
+    /// Called when a response has been received, including the error statuses
+    fn after_receive(&self, context: &RequestContext, response: &HttpResponse) {
+        let _ = (context, response);
+    }
+
+    /// Called when the request failed (transport error or error status)
+    fn on_error(&self, context: &RequestContext, error: &Error) {
+        let _ = (context, error);
+    }
+}
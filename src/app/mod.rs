@@ -5,7 +5,17 @@
 mod agent;
 pub mod auth;
 pub mod builder;
+pub mod cassette;
 pub mod client;
 mod constant;
+pub mod cost_basis;
 pub mod error;
+#[cfg(feature = "export")]
+pub mod export;
+pub mod metrics;
+pub mod middleware;
+pub mod portfolio;
 pub mod response;
+pub mod retry;
+pub mod sync;
+pub mod transport;
@@ -0,0 +1,177 @@
+//! Portfolio valuation
+//!
+//! Aggregates the balances of the accounts per currency, valued in a target currency.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::response::{Account, ExchangeRates};
+
+/// Fiat account type
+const FIAT: &str = "fiat";
+/// Vault account type
+const VAULT: &str = "vault";
+
+/// Holdings of a currency, across all the accounts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PortfolioEntry {
+    /// Currency code (i.e., BTC)
+    pub currency: String,
+    /// Total balance
+    pub balance: f64,
+    /// Part of the balance held in vault accounts
+    pub vault_balance: f64,
+    /// Fiat currency
+    pub fiat: bool,
+    /// Value of the balance in the target currency
+    ///
+    /// `None` if there is no exchange rate for the currency.
+    pub value: Option<f64>,
+}
+
+/// Portfolio, valued in a target currency
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Portfolio {
+    /// Target currency (i.e., USD)
+    pub currency: String,
+    /// Holdings per currency, from the highest value (the unvalued ones last)
+    pub entries: Vec<PortfolioEntry>,
+    /// Total value, of the valued entries
+    pub total: f64,
+}
+
+impl Portfolio {
+    /// Aggregate the accounts with the exchange rates of the target currency
+    ///
+    /// The zero balances are skipped.
+    pub fn new(accounts: &[Account], rates: &ExchangeRates) -> Self {
+        let mut entries: BTreeMap<&str, PortfolioEntry> = BTreeMap::new();
+
+        for account in accounts.iter().filter(|a| a.balance.amount != 0.0) {
+            let currency: &str = account.balance.currency.as_str();
+
+            let entry: &mut PortfolioEntry =
+                entries.entry(currency).or_insert_with(|| PortfolioEntry {
+                    currency: currency.to_string(),
+                    balance: 0.0,
+                    vault_balance: 0.0,
+                    fiat: false,
+                    value: None,
+                });
+
+            entry.balance += account.balance.amount;
+
+            match account.r#type.as_str() {
+                VAULT => entry.vault_balance += account.balance.amount,
+                FIAT => entry.fiat = true,
+                _ => {}
+            }
+        }
+
+        let mut entries: Vec<PortfolioEntry> = entries
+            .into_values()
+            .map(|mut entry| {
+                entry.value = rates.value_of(&entry.currency, entry.balance);
+                entry
+            })
+            .collect();
+
+        // From the highest value, the unvalued ones last
+        entries.sort_by(|a, b| {
+            b.value
+                .unwrap_or(f64::NEG_INFINITY)
+                .total_cmp(&a.value.unwrap_or(f64::NEG_INFINITY))
+        });
+
+        Self {
+            currency: rates.currency.clone(),
+            total: entries.iter().filter_map(|e| e.value).sum(),
+            entries,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn account(id: &str, r#type: &str, currency: &str, amount: &str) -> Account {
+        serde_json::from_value(json!({
+            "id": id,
+            "name": id,
+            "primary": false,
+            "type": r#type,
+            "currency": {"asset_id": currency, "code": currency, "name": currency},
+            "balance": {"amount": amount, "currency": currency},
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_portfolio() {
+        let accounts = vec![
+            account("btc", "wallet", "BTC", "0.5"),
+            account("btc-vault", "vault", "BTC", "1.5"),
+            account("eth", "wallet", "ETH", "0"),
+            account("usd", "fiat", "USD", "100.00"),
+            account("eur", "fiat", "EUR", "40.00"),
+            account("xyz", "wallet", "XYZ", "10"),
+        ];
+        let rates: ExchangeRates = serde_json::from_value(json!({
+            "currency": "USD",
+            "rates": {"BTC": "0.0000152587890625", "ETH": "0.0005", "EUR": "0.5", "USD": "1.0"},
+        }))
+        .unwrap();
+
+        let portfolio = Portfolio::new(&accounts, &rates);
+        assert_eq!(portfolio.currency, "USD");
+
+        let currencies: Vec<&str> = portfolio
+            .entries
+            .iter()
+            .map(|e| e.currency.as_str())
+            .collect();
+        assert_eq!(currencies, ["BTC", "USD", "EUR", "XYZ"]);
+
+        let btc: &PortfolioEntry = &portfolio.entries[0];
+        assert_eq!(btc.balance, 2.0);
+        assert_eq!(btc.vault_balance, 1.5);
+        assert!(!btc.fiat);
+        assert_eq!(btc.value, Some(131_072.0));
+
+        assert!(portfolio.entries[2].fiat);
+        assert_eq!(portfolio.entries[3].value, None);
+        assert_eq!(portfolio.total, 131_252.0);
+    }
+
+    #[cfg(all(feature = "reqwest", feature = "testing"))]
+    #[tokio::test]
+    async fn test_portfolio_value() {
+        use crate::app::client::CoinbaseAppClient;
+        use crate::testing::fixtures;
+        use crate::testing::server::MockServer;
+
+        let server = MockServer::start().await.unwrap();
+        for (id, currency) in [("btc", "BTC"), ("eth", "ETH"), ("usd", "USD")] {
+            server.add_account(fixtures::account(id, currency, "1.5"));
+        }
+        server.set_exchange_rates("USD", &[("BTC", "0.5"), ("ETH", "0.25"), ("USD", "1")]);
+
+        let client = CoinbaseAppClient::builder()
+            .base_url(server.url().clone())
+            .build()
+            .unwrap();
+
+        let portfolio = client.portfolio_value("USD").await.unwrap();
+        assert_eq!(portfolio.entries.len(), 3);
+        assert_eq!(portfolio.entries[0].currency, "ETH");
+        assert_eq!(portfolio.total, 1.5 / 0.5 + 1.5 / 0.25 + 1.5);
+        assert_eq!(
+            server.requests().last().unwrap(),
+            "GET /v2/exchange-rates?currency=USD"
+        );
+    }
+}
@@ -2,10 +2,19 @@
 //!
 //! <https://docs.cdp.coinbase.com/coinbase-app/introduction/welcome>
 
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
 use std::fmt;
+use std::ops::Deref;
 
 use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use super::error::Error;
+use crate::util::de::{self, deserialize_string_map_to_f64, deserialize_string_to_f64};
+use crate::util::ser::{serialize_f64_map_to_string, serialize_f64_to_string};
 
 /// Coinbase App error message
 ///
@@ -24,6 +33,12 @@ impl fmt::Display for CoinbaseErrorMessage {
     }
 }
 
+/// Coinbase App error envelope
+#[derive(Deserialize)]
+pub(super) struct CoinbaseErrorResponse {
+    pub errors: Vec<CoinbaseErrorMessage>,
+}
+
 #[derive(Deserialize)]
 pub(super) struct CoinbaseResponse<T> {
     pub pagination: Option<Pagination>,
@@ -46,12 +61,52 @@ pub(super) struct Pagination {
     // pub next_starting_after: Option<String>,
     // pub limit: usize,
     // pub order: Order,
-    // pub previous_uri: Option<String>,
+    pub previous_uri: Option<String>,
     pub next_uri: Option<String>,
 }
 
+/// Fields not modeled by a response type (i.e., `details`, `network` or `to` of a transaction)
+///
+/// Allows reading the fields added by Coinbase before they are supported.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Extra(Map<String, Value>);
+
+impl Extra {
+    /// Deserialize a field
+    ///
+    /// Returns `None` if the field is missing.
+    pub fn get_as<T>(&self, key: &str) -> Option<Result<T, Error>>
+    where
+        T: DeserializeOwned,
+    {
+        self.0.get(key).cloned().map(de::from_value)
+    }
+
+    /// Get the fields
+    #[inline]
+    pub fn into_inner(self) -> Map<String, Value> {
+        self.0
+    }
+}
+
+impl Deref for Extra {
+    type Target = Map<String, Value>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// The JSON values have no ordering: the fields are only comparable when equal.
+impl PartialOrd for Extra {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        (self == other).then_some(Ordering::Equal)
+    }
+}
+
 /// Account
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
 pub struct Account {
     // NOTE: the ID appears to be either a UUID or a token name e.g: "BTC"
     /// Account ID
@@ -72,20 +127,30 @@ pub struct Account {
     pub created_at: Option<DateTime<Utc>>,
     /// Updated at
     pub updated_at: Option<DateTime<Utc>>,
+    /// Other fields
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 /// Account balance
-#[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize)]
+#[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
 pub struct Balance {
     /// Amount
-    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    ///
+    /// Serialized in its shortest form: `"39.59000000"` is written back as `"39.59"`.
+    #[serde(
+        serialize_with = "serialize_f64_to_string",
+        deserialize_with = "deserialize_string_to_f64"
+    )]
     pub amount: f64,
     /// Currency
     pub currency: String,
 }
 
 /// Currency
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+///
+/// The other fields of the API (i.e., `exponent` or `color`) are not kept, so not serialized.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Currency {
     /// Asset ID
     pub asset_id: String,
@@ -95,8 +160,43 @@ pub struct Currency {
     pub name: String,
 }
 
+/// Exchange rates of a currency
+///
+/// <https://docs.cdp.coinbase.com/coinbase-app/track-apis/exchange-rates>
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExchangeRates {
+    /// Base currency (i.e., USD)
+    pub currency: String,
+    /// Amount of every currency for 1 unit of the base currency
+    ///
+    /// Serialized in their shortest form, like [`Balance::amount`].
+    #[serde(
+        serialize_with = "serialize_f64_map_to_string",
+        deserialize_with = "deserialize_string_map_to_f64"
+    )]
+    pub rates: BTreeMap<String, f64>,
+}
+
+impl ExchangeRates {
+    /// Value of an amount in the base currency
+    ///
+    /// Returns `None` if the rate is unknown.
+    pub fn value_of(&self, currency: &str, amount: f64) -> Option<f64> {
+        if currency == self.currency {
+            return Some(amount);
+        }
+
+        match self.rates.get(currency) {
+            Some(rate) if *rate > 0.0 => Some(amount / rate),
+            _ => None,
+        }
+    }
+}
+
 /// Transaction type
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
 pub enum TransactionType {
     /// Fills for an advanced trade order
     #[serde(rename = "advanced_trade_fill")]
@@ -188,8 +288,23 @@ pub enum TransactionType {
     FcmFuturesUsdcSellAdditionalEncumbermentRollup,
 }
 
+impl TransactionType {
+    /// Check if the transaction moves funds between the user's own accounts (i.e., `transfer`, `staking_transfer`)
+    pub fn is_internal_transfer(&self) -> bool {
+        matches!(
+            self,
+            Self::Transfer
+                | Self::StakingTransfer
+                | Self::UnstakingTransfer
+                | Self::VaultWithdrawal
+                | Self::IntxDeposit
+                | Self::IntxWithdrawal
+        )
+    }
+}
+
 /// Transaction status
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum TransactionStatus {
     /// Transaction was canceled
     #[serde(rename = "canceled")]
@@ -214,8 +329,18 @@ pub enum TransactionStatus {
     WaitingForSignature,
 }
 
+impl TransactionStatus {
+    /// Check if the status is final (i.e., `completed` or `failed`)
+    pub fn is_terminal(&self) -> bool {
+        match self {
+            Self::Canceled | Self::Completed | Self::Expired | Self::Failed => true,
+            Self::Pending | Self::WaitingForClearing | Self::WaitingForSignature => false,
+        }
+    }
+}
+
 /// Transaction
-#[derive(Debug, Clone, PartialEq, PartialOrd, Deserialize)]
+#[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
 pub struct Transaction {
     /// Transaction ID
     pub id: String,
@@ -235,14 +360,9 @@ pub struct Transaction {
     pub description: Option<String>,
     /// Created at
     pub created_at: DateTime<Utc>,
-}
-
-fn deserialize_string_to_f64<'de, D>(deserializer: D) -> Result<f64, D::Error>
-where
-    D: serde::Deserializer<'de>,
-{
-    let s: String = String::deserialize(deserializer)?;
-    s.parse().map_err(serde::de::Error::custom)
+    /// Other fields
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 #[cfg(test)]
@@ -250,6 +370,7 @@ mod tests {
     use super::*;
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_deserialize_account() {
         let json = r##"
         {
@@ -280,6 +401,7 @@ mod tests {
           }
         }"##;
 
+        let fixture: Value = serde_json::from_str(json).unwrap();
         let response: CoinbaseResponse<Account> = serde_json::from_str(json).unwrap();
         let account = response.data;
 
@@ -304,6 +426,25 @@ mod tests {
         // Verify optional fields
         assert_eq!(account.created_at.map(|t| t.timestamp()), Some(1706734142));
         assert_eq!(account.updated_at.map(|t| t.timestamp()), Some(1706734142));
+
+        // Verify extra fields
+        assert_eq!(account.extra["resource"], "account");
+        assert!(!account.extra.contains_key("balance"));
+
+        // Round trip: the amount padding and the unmodeled currency fields are not kept,
+        // the other fields are written back as received
+        let value: Value = serde_json::to_value(&account).unwrap();
+        for (key, field) in fixture["data"].as_object().unwrap() {
+            if key != "balance" && key != "currency" {
+                assert_eq!(&value[key], field, "{key}");
+            }
+        }
+        assert_eq!(value["balance"]["currency"], "BTC");
+        assert_eq!(
+            value["currency"]["asset_id"],
+            fixture["data"]["currency"]["asset_id"]
+        );
+        assert_eq!(serde_json::from_value::<Account>(value).unwrap(), account);
     }
 
     #[test]
@@ -449,5 +590,71 @@ mod tests {
         assert_eq!(tx3.status, TransactionStatus::Completed);
         assert_eq!(tx3.amount.amount, -5.0);
         assert_eq!(tx3.native_amount.amount, -50.0);
+
+        // Extra fields
+        assert_eq!(tx3.extra["to"]["resource"], "account");
+        let updated_at: DateTime<Utc> = tx3.extra.get_as("updated_at").unwrap().unwrap();
+        assert_eq!(updated_at.timestamp(), 1422737342);
+        assert!(tx3.extra.get_as::<String>("network").is_none());
+        assert_eq!(transactions[3].extra["network"]["name"], "bitcoin");
+
+        // Round trip, with the wire format
+        for tx in transactions.iter() {
+            let value: Value = serde_json::to_value(tx).unwrap();
+            assert!(value["amount"]["amount"].is_string());
+            assert_eq!(serde_json::from_value::<Transaction>(value).unwrap(), *tx);
+        }
+
+        let value: Value = serde_json::to_value(&transactions[3]).unwrap();
+        assert_eq!(value["type"], "send");
+        assert_eq!(value["status"], "completed");
+        assert_eq!(value["amount"]["amount"], "-0.001");
+        assert_eq!(value["native_amount"]["amount"], "-0.01");
+        assert_eq!(value["description"], Value::Null);
+    }
+
+    #[cfg(all(feature = "reqwest", feature = "testing"))]
+    #[tokio::test]
+    async fn test_lenient() {
+        use serde_json::json;
+
+        use crate::app::client::CoinbaseAppClient;
+        use crate::testing::fixtures;
+        use crate::testing::server::MockServer;
+
+        let server = MockServer::builder().page_size(2).start().await.unwrap();
+        server.add_account(fixtures::account("btc", "BTC", "1.5"));
+        server.add_transaction(
+            "btc",
+            fixtures::transaction("tx1", "buy", "0.1", "BTC", "100.00"),
+        );
+        let mut invalid: Value = fixtures::transaction("tx2", "buy", "0.2", "BTC", "200.00");
+        invalid["amount"]["amount"] = json!(0.2);
+        server.add_transaction("btc", invalid);
+        server.add_transaction(
+            "btc",
+            fixtures::transaction("tx3", "sell", "0.1", "BTC", "110.00"),
+        );
+
+        let client = CoinbaseAppClient::builder()
+            .base_url(server.url().clone())
+            .build()
+            .unwrap();
+
+        // Strict
+        match client.transactions("btc").await.unwrap_err().inner() {
+            Error::Decode { path, .. } => assert_eq!(path, "data[1].amount.amount"),
+            e => panic!("unexpected error: {e:?}"),
+        }
+
+        // Lenient
+        let transactions = client.transactions_lenient("btc").await.unwrap();
+        assert_eq!(transactions.len(), 3);
+        assert_eq!(transactions[0].as_ref().unwrap().id, "tx1");
+        match &transactions[1] {
+            Err(Error::Decode { path, .. }) => assert_eq!(path, "[1].amount.amount"),
+            res => panic!("unexpected result: {res:?}"),
+        }
+        assert_eq!(transactions[2].as_ref().unwrap().id, "tx3");
     }
 }
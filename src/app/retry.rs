@@ -0,0 +1,149 @@
+//! Retry policy
+//!
+//! Opt-in retries of the rate-limited (`429`) and server error (`5xx`) responses,
+//! with exponential backoff. The `Retry-After` header, when present, takes precedence
+//! (capped to the max delay).
+
+use std::time::Duration;
+
+use http::StatusCode;
+
+/// Retry policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RetryPolicy {
+    /// Max number of retries, after the first attempt (default: 3)
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for the next ones (default: 500 ms)
+    pub base_delay: Duration,
+    /// Max delay before a retry, including the `Retry-After` ones (default: 30 secs)
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// New policy with a max number of retries
+    #[inline]
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            ..Default::default()
+        }
+    }
+
+    /// Set the delay before the first retry
+    #[inline]
+    pub fn base_delay(mut self, delay: Duration) -> Self {
+        self.base_delay = delay;
+        self
+    }
+
+    /// Set the max delay before a retry
+    #[inline]
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Check if the response of the attempt (starting at `0`) must be retried.
+    pub(crate) fn should_retry(&self, attempt: u32, status: StatusCode) -> bool {
+        attempt < self.max_retries
+            && (status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error())
+    }
+
+    /// Delay before retrying the attempt (starting at `0`).
+    pub(crate) fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        match retry_after {
+            Some(retry_after) => retry_after.min(self.max_delay),
+            None => self
+                .base_delay
+                .saturating_mul(2u32.saturating_pow(attempt))
+                .min(self.max_delay),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_policy() {
+        let policy = RetryPolicy::new(2).base_delay(Duration::from_secs(1));
+
+        assert!(policy.should_retry(0, StatusCode::TOO_MANY_REQUESTS));
+        assert!(policy.should_retry(1, StatusCode::BAD_GATEWAY));
+        assert!(!policy.should_retry(2, StatusCode::BAD_GATEWAY));
+        assert!(!policy.should_retry(0, StatusCode::NOT_FOUND));
+
+        assert_eq!(policy.delay(0, None), Duration::from_secs(1));
+        assert_eq!(policy.delay(2, None), Duration::from_secs(4));
+        assert_eq!(policy.delay(10, None), Duration::from_secs(30));
+        assert_eq!(
+            policy.delay(0, Some(Duration::from_secs(10))),
+            Duration::from_secs(10)
+        );
+        assert_eq!(
+            policy.delay(0, Some(Duration::from_secs(600))),
+            Duration::from_secs(30)
+        );
+    }
+
+    #[cfg(all(feature = "reqwest", feature = "testing"))]
+    #[tokio::test]
+    async fn test_retry() {
+        use base64::Engine;
+        use base64::engine::general_purpose::STANDARD;
+        use ring::signature::{Ed25519KeyPair, KeyPair};
+
+        use crate::app::auth::CoinbaseAuth;
+        use crate::app::client::CoinbaseAppClient;
+        use crate::testing::fixtures;
+        use crate::testing::server::{JwtPublicKey, MockFailure, MockServer};
+
+        // The JWTs of the retries are verified too
+        let seed: [u8; 32] = [7u8; 32];
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(&seed).unwrap();
+        let server = MockServer::builder()
+            .public_key(JwtPublicKey::Ed25519(
+                key_pair.public_key().as_ref().to_vec(),
+            ))
+            .start()
+            .await
+            .unwrap();
+        server.add_account(fixtures::account("btc", "BTC", "1.5"));
+
+        let client = CoinbaseAppClient::builder()
+            .auth(CoinbaseAuth::ApiKeys {
+                api_key: String::from("organizations/org/apiKeys/key"),
+                secret_key: STANDARD.encode(seed).into(),
+            })
+            .base_url(server.url().clone())
+            .retry(RetryPolicy::new(2).base_delay(Duration::from_millis(10)))
+            .build()
+            .unwrap();
+
+        server.fail_next(MockFailure::RateLimited { retry_after: 0 });
+        server.fail_next(MockFailure::Error {
+            status: 503,
+            id: String::from("service_unavailable"),
+            message: String::from("Service unavailable"),
+        });
+        assert_eq!(client.account("btc").await.unwrap().id, "btc");
+        assert_eq!(server.requests().len(), 3);
+
+        // Retries exhausted
+        for _ in 0..3 {
+            server.fail_next(MockFailure::RateLimited { retry_after: 0 });
+        }
+        assert!(client.account("btc").await.is_err());
+    }
+}
@@ -0,0 +1,379 @@
+//! Incremental transaction sync
+//!
+//! [`TransactionSync`] fetches only the transactions created since the last sync of an account,
+//! and re-checks the pending ones until they reach a terminal [`TransactionStatus`](super::response::TransactionStatus).
+//! The progress of every account is persisted in a [`CursorStore`].
+//!
+//! If the newest transaction seen is no longer listed, the new transactions are found by creation time instead,
+//! and the pending transactions that no longer exist are dropped.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::{fmt, fs};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::client::CoinbaseAppClient;
+use super::error::Error;
+use super::response::Transaction;
+
+/// Sync progress of an account
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncCursor {
+    /// ID of the newest transaction seen
+    pub newest_id: Option<String>,
+    /// Creation time of the newest transaction seen
+    pub newest_at: Option<DateTime<Utc>>,
+    /// IDs of the transactions not in a terminal status yet
+    #[serde(default)]
+    pub pending: Vec<String>,
+}
+
+/// Cursor store
+pub trait CursorStore: fmt::Debug + Send + Sync {
+    /// Load the cursor of an account
+    ///
+    /// Returns `None` if the account has never been synced.
+    fn load(&self, account_id: &str) -> Result<Option<SyncCursor>, Error>;
+
+    /// Save the cursor of an account
+    fn save(&self, account_id: &str, cursor: &SyncCursor) -> Result<(), Error>;
+}
+
+/// In-memory cursor store
+#[derive(Debug, Default)]
+pub struct MemoryCursorStore {
+    cursors: Mutex<HashMap<String, SyncCursor>>,
+}
+
+impl MemoryCursorStore {
+    /// New empty store
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl CursorStore for MemoryCursorStore {
+    fn load(&self, account_id: &str) -> Result<Option<SyncCursor>, Error> {
+        let cursors = self.cursors.lock().expect("cursor store lock poisoned");
+        Ok(cursors.get(account_id).cloned())
+    }
+
+    fn save(&self, account_id: &str, cursor: &SyncCursor) -> Result<(), Error> {
+        let mut cursors = self.cursors.lock().expect("cursor store lock poisoned");
+        cursors.insert(account_id.to_string(), cursor.clone());
+        Ok(())
+    }
+}
+
+/// File cursor store
+///
+/// The cursors of all the accounts are stored in a JSON file, replaced atomically on every save.
+#[derive(Debug)]
+pub struct FileCursorStore {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl FileCursorStore {
+    /// New store, the file is created on the first save
+    pub fn new<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// File path
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn read(&self) -> Result<HashMap<String, SyncCursor>, Error> {
+        if !self.path.exists() {
+            return Ok(HashMap::new());
+        }
+
+        let json: String = fs::read_to_string(&self.path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+}
+
+impl CursorStore for FileCursorStore {
+    fn load(&self, account_id: &str) -> Result<Option<SyncCursor>, Error> {
+        let _guard = self.lock.lock().expect("cursor store lock poisoned");
+        Ok(self.read()?.remove(account_id))
+    }
+
+    fn save(&self, account_id: &str, cursor: &SyncCursor) -> Result<(), Error> {
+        let _guard = self.lock.lock().expect("cursor store lock poisoned");
+
+        let mut cursors: HashMap<String, SyncCursor> = self.read()?;
+        cursors.insert(account_id.to_string(), cursor.clone());
+
+        // Write to a temporary file first, to never leave a truncated file
+        let tmp: PathBuf = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(&cursors)?)?;
+        fs::rename(&tmp, &self.path)?;
+
+        Ok(())
+    }
+}
+
+/// Result of the sync of an account
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SyncReport {
+    /// Transactions created since the last sync, from the newest
+    pub new: Vec<Transaction>,
+    /// Previously pending transactions that reached a terminal status
+    pub settled: Vec<Transaction>,
+    /// Number of transactions still pending
+    pub pending: usize,
+    /// IDs of the previously pending transactions that no longer exist
+    pub dropped: Vec<String>,
+}
+
+/// Incremental transaction sync
+#[derive(Debug, Clone)]
+pub struct TransactionSync {
+    client: CoinbaseAppClient,
+    store: Arc<dyn CursorStore>,
+}
+
+impl TransactionSync {
+    /// New sync, with a cursor store
+    #[inline]
+    pub fn new(client: CoinbaseAppClient, store: Arc<dyn CursorStore>) -> Self {
+        Self { client, store }
+    }
+
+    /// Sync the transactions of an account
+    ///
+    /// The first sync fetches the whole history. The cursor is saved only if the sync succeeds.
+    pub async fn sync(&self, account_id: &str) -> Result<SyncReport, Error> {
+        let mut cursor: SyncCursor = self.store.load(account_id)?.unwrap_or_default();
+
+        // New transactions
+        let new: Vec<Transaction> = match (&cursor.newest_id, cursor.newest_at) {
+            (Some(newest_id), newest_at) => {
+                match self.client.transactions_since(account_id, newest_id).await {
+                    Ok(new) => new,
+                    // The cursor transaction is no longer listed: filter by creation time
+                    Err(e) if is_invalid_cursor(&e) && newest_at.is_some() => {
+                        #[cfg(feature = "tracing")]
+                        tracing::warn!(error = %e.inner(), "sync cursor not found, filtering by creation time");
+
+                        self.client
+                            .transactions(account_id)
+                            .await?
+                            .into_iter()
+                            .filter(|tx| Some(tx.created_at) > newest_at)
+                            .collect()
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            (None, _) => self.client.transactions(account_id).await?,
+        };
+
+        // Re-check the pending transactions
+        let mut settled: Vec<Transaction> = Vec::new();
+        let mut pending: Vec<String> = Vec::with_capacity(cursor.pending.len());
+        let mut dropped: Vec<String> = Vec::new();
+
+        for id in cursor.pending.iter() {
+            let transaction: Transaction = match self.client.transaction(account_id, id).await {
+                Ok(transaction) => transaction,
+                Err(e) if e.status() == Some(404) => {
+                    dropped.push(id.clone());
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+
+            if transaction.status.is_terminal() {
+                settled.push(transaction);
+            } else {
+                pending.push(transaction.id);
+            }
+        }
+
+        pending.extend(
+            new.iter()
+                .filter(|tx| !tx.status.is_terminal())
+                .map(|tx| tx.id.clone()),
+        );
+
+        // The list starts from the newest
+        if let Some(newest) = new.first() {
+            cursor.newest_id = Some(newest.id.clone());
+            cursor.newest_at = Some(newest.created_at);
+        }
+        cursor.pending = pending;
+
+        self.store.save(account_id, &cursor)?;
+
+        Ok(SyncReport {
+            pending: cursor.pending.len(),
+            new,
+            settled,
+            dropped,
+        })
+    }
+}
+
+/// Check if the error is caused by an unknown pagination cursor.
+#[inline]
+fn is_invalid_cursor(error: &Error) -> bool {
+    error.status() == Some(404)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_cursor_store() {
+        let path: PathBuf =
+            std::env::temp_dir().join(format!("coinbase-api-cursors-{}.json", std::process::id()));
+        let store = FileCursorStore::new(&path);
+        assert_eq!(store.load("btc").unwrap(), None);
+
+        let cursor = SyncCursor {
+            newest_id: Some(String::from("tx-2")),
+            newest_at: Some(Utc::now()),
+            pending: vec![String::from("tx-1")],
+        };
+        store.save("btc", &cursor).unwrap();
+        store.save("eth", &SyncCursor::default()).unwrap();
+
+        // Reopen
+        let store = FileCursorStore::new(&path);
+        assert_eq!(store.load("btc").unwrap(), Some(cursor));
+        assert_eq!(store.load("eth").unwrap(), Some(SyncCursor::default()));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(all(feature = "reqwest", feature = "testing"))]
+    #[tokio::test]
+    async fn test_sync() {
+        use crate::app::response::TransactionStatus;
+        use crate::testing::fixtures::{self, transaction_at};
+        use crate::testing::server::MockServer;
+
+        let server = MockServer::builder().page_size(2).start().await.unwrap();
+        server.add_account(fixtures::account("btc", "BTC", "1.5"));
+        server.add_transaction(
+            "btc",
+            transaction_at("tx-1", "completed", "2024-01-01T00:00:00Z"),
+        );
+        server.add_transaction(
+            "btc",
+            transaction_at("tx-2", "pending", "2024-01-02T00:00:00Z"),
+        );
+
+        let client = CoinbaseAppClient::builder()
+            .base_url(server.url().clone())
+            .build()
+            .unwrap();
+        let store = Arc::new(MemoryCursorStore::new());
+        let sync = TransactionSync::new(client, store.clone());
+
+        // Full history
+        let report: SyncReport = sync.sync("btc").await.unwrap();
+        let ids: Vec<&str> = report.new.iter().map(|tx| tx.id.as_str()).collect();
+        assert_eq!(ids, ["tx-2", "tx-1"]);
+        assert_eq!(report.pending, 1);
+
+        // Only the new transactions, on several pages
+        for (id, day) in [("tx-3", 3), ("tx-4", 4), ("tx-5", 5)] {
+            let created_at: String = format!("2024-01-0{day}T00:00:00Z");
+            server.add_transaction("btc", transaction_at(id, "completed", &created_at));
+        }
+        server.set_transaction_status("btc", "tx-2", "completed");
+
+        let report: SyncReport = sync.sync("btc").await.unwrap();
+        let ids: Vec<&str> = report.new.iter().map(|tx| tx.id.as_str()).collect();
+        assert_eq!(ids, ["tx-5", "tx-4", "tx-3"]);
+        assert_eq!(report.settled.len(), 1);
+        assert_eq!(report.settled[0].status, TransactionStatus::Completed);
+        assert_eq!(report.pending, 0);
+
+        let cursor: SyncCursor = store.load("btc").unwrap().unwrap();
+        assert_eq!(cursor.newest_id.as_deref(), Some("tx-5"));
+
+        // Nothing new
+        let report: SyncReport = sync.sync("btc").await.unwrap();
+        assert!(report.new.is_empty());
+        assert!(
+            server
+                .requests()
+                .iter()
+                .any(|r| r.contains("ending_before=tx-2"))
+        );
+    }
+
+    #[cfg(all(feature = "reqwest", feature = "testing"))]
+    #[tokio::test]
+    async fn test_sync_recovery() {
+        use crate::testing::fixtures::{self, transaction_at};
+        use crate::testing::server::MockServer;
+
+        let server = MockServer::start().await.unwrap();
+        server.add_account(fixtures::account("btc", "BTC", "1.5"));
+        server.add_transaction(
+            "btc",
+            transaction_at("tx-1", "pending", "2024-01-01T00:00:00Z"),
+        );
+        server.add_transaction(
+            "btc",
+            transaction_at("tx-2", "completed", "2024-01-02T00:00:00Z"),
+        );
+
+        let client = CoinbaseAppClient::builder()
+            .base_url(server.url().clone())
+            .build()
+            .unwrap();
+        let store = Arc::new(MemoryCursorStore::new());
+        let sync = TransactionSync::new(client, store.clone());
+
+        let report: SyncReport = sync.sync("btc").await.unwrap();
+        assert_eq!(report.new.len(), 2);
+        assert_eq!(report.pending, 1);
+
+        // The cursor and the pending transactions are deleted
+        server.remove_transaction("btc", "tx-2");
+        server.remove_transaction("btc", "tx-1");
+        server.add_transaction(
+            "btc",
+            transaction_at("tx-3", "completed", "2024-01-03T00:00:00Z"),
+        );
+
+        let report: SyncReport = sync.sync("btc").await.unwrap();
+        let ids: Vec<&str> = report.new.iter().map(|tx| tx.id.as_str()).collect();
+        assert_eq!(ids, ["tx-3"]);
+        assert_eq!(report.dropped, ["tx-1"]);
+        assert_eq!(report.pending, 0);
+
+        let cursor: SyncCursor = store.load("btc").unwrap().unwrap();
+        assert_eq!(cursor.newest_id.as_deref(), Some("tx-3"));
+        assert!(cursor.pending.is_empty());
+
+        // Back to the cursor
+        server.add_transaction(
+            "btc",
+            transaction_at("tx-4", "completed", "2024-01-04T00:00:00Z"),
+        );
+        let report: SyncReport = sync.sync("btc").await.unwrap();
+        let ids: Vec<&str> = report.new.iter().map(|tx| tx.id.as_str()).collect();
+        assert_eq!(ids, ["tx-4"]);
+    }
+}
@@ -0,0 +1,151 @@
+//! HTTP transport
+//!
+//! The [`HttpTransport`] trait decouples the client from the HTTP library:
+//! [`ReqwestTransport`] is the default implementation (`reqwest` feature).
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use http::header::RETRY_AFTER;
+use http::{HeaderMap, Method, StatusCode};
+#[cfg(feature = "reqwest")]
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+use url::Url;
+
+use super::error::Error;
+use crate::util::de;
+
+/// Response headers holding the request ID, in order of preference
+const REQUEST_ID_HEADERS: [&str; 2] = ["cb-request-id", "x-request-id"];
+
+/// Boxed future, returned by the [`HttpTransport`]
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// HTTP request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpRequest {
+    /// Method
+    pub method: Method,
+    /// URL
+    pub url: Url,
+    /// Headers
+    pub headers: HeaderMap,
+    /// Body
+    pub body: Option<Vec<u8>>,
+}
+
+impl HttpRequest {
+    /// New request without headers and body
+    #[inline]
+    pub fn new(method: Method, url: Url) -> Self {
+        Self {
+            method,
+            url,
+            headers: HeaderMap::new(),
+            body: None,
+        }
+    }
+}
+
+/// HTTP response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpResponse {
+    /// Status code
+    pub status: StatusCode,
+    /// Headers
+    pub headers: HeaderMap,
+    /// Body
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /// Deserialize the JSON body
+    ///
+    /// The errors report the path of the failing field (see [`Error::Decode`]).
+    #[inline]
+    pub fn json<T>(&self) -> Result<T, Error>
+    where
+        T: DeserializeOwned,
+    {
+        de::from_slice(&self.body)
+    }
+
+    /// Request ID, assigned by Coinbase (useful for the support tickets)
+    pub fn request_id(&self) -> Option<&str> {
+        REQUEST_ID_HEADERS
+            .iter()
+            .find_map(|name| self.headers.get(*name)?.to_str().ok())
+    }
+
+    /// Delay of the `Retry-After` header (only the delay in seconds is supported)
+    pub fn retry_after(&self) -> Option<Duration> {
+        let secs: u64 = self
+            .headers
+            .get(RETRY_AFTER)?
+            .to_str()
+            .ok()?
+            .trim()
+            .parse()
+            .ok()?;
+        Some(Duration::from_secs(secs))
+    }
+}
+
+/// HTTP transport
+pub trait HttpTransport: fmt::Debug + Send + Sync {
+    /// Send the request and read the whole response
+    ///
+    /// Non-success status codes must be returned as responses, not as errors.
+    fn send(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, Error>>;
+}
+
+/// [`reqwest`] transport
+#[cfg(feature = "reqwest")]
+#[derive(Debug, Clone, Default)]
+pub struct ReqwestTransport {
+    client: Client,
+}
+
+#[cfg(feature = "reqwest")]
+impl ReqwestTransport {
+    /// New transport with a requests timeout
+    pub fn new(timeout: Duration) -> Result<Self, Error> {
+        Ok(Self {
+            client: Client::builder().timeout(timeout).build()?,
+        })
+    }
+}
+
+#[cfg(feature = "reqwest")]
+impl From<Client> for ReqwestTransport {
+    fn from(client: Client) -> Self {
+        Self { client }
+    }
+}
+
+#[cfg(feature = "reqwest")]
+impl HttpTransport for ReqwestTransport {
+    fn send(&self, request: HttpRequest) -> BoxFuture<'_, Result<HttpResponse, Error>> {
+        Box::pin(async move {
+            let mut builder = self
+                .client
+                .request(request.method, request.url)
+                .headers(request.headers);
+
+            if let Some(body) = request.body {
+                builder = builder.body(body);
+            }
+
+            let response = builder.send().await?;
+
+            Ok(HttpResponse {
+                status: response.status(),
+                headers: response.headers().clone(),
+                body: response.bytes().await?.to_vec(),
+            })
+        })
+    }
+}
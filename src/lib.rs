@@ -5,6 +5,10 @@
 #![warn(clippy::large_futures)]
 #![warn(rustdoc::bare_urls)]
 
+#[cfg(feature = "ws")]
+pub mod advanced;
 pub mod app;
 pub mod prelude;
+#[cfg(feature = "testing")]
+pub mod testing;
 mod util;
@@ -7,9 +7,38 @@
 
 pub use ::url::*;
 
+#[cfg(feature = "ws")]
+pub use crate::advanced::ws::book::*;
+#[cfg(feature = "ws")]
+pub use crate::advanced::ws::builder::*;
+#[cfg(feature = "ws")]
+pub use crate::advanced::ws::channel::*;
+#[cfg(feature = "ws")]
+pub use crate::advanced::ws::client::*;
+#[cfg(feature = "ws")]
+pub use crate::advanced::ws::message::*;
+#[cfg(feature = "ws")]
+pub use crate::advanced::ws::reconnect::*;
+pub use crate::app::auth::cache::*;
+pub use crate::app::auth::jwt::*;
+pub use crate::app::auth::oauth2::*;
+pub use crate::app::auth::secret::*;
+pub use crate::app::auth::signer::*;
 pub use crate::app::auth::*;
 pub use crate::app::builder::*;
+pub use crate::app::cassette::*;
 pub use crate::app::client::*;
+pub use crate::app::cost_basis::*;
 pub use crate::app::error::*;
+#[cfg(feature = "export")]
+pub use crate::app::export::*;
+pub use crate::app::metrics::*;
+pub use crate::app::middleware::*;
+pub use crate::app::portfolio::*;
 pub use crate::app::response::*;
+pub use crate::app::retry::*;
+pub use crate::app::sync::*;
+pub use crate::app::transport::*;
+#[cfg(feature = "testing")]
+pub use crate::testing::server::*;
 pub use crate::*;
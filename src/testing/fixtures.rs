@@ -0,0 +1,57 @@
+//! JSON fixtures for the mock server
+
+use serde_json::{Value, json};
+
+/// Account with the provided balance (i.e., `account("btc-wallet", "BTC", "0.5")`)
+pub fn account(id: &str, currency: &str, amount: &str) -> Value {
+    json!({
+        "id": id,
+        "name": format!("{currency} Wallet"),
+        "primary": false,
+        "type": "wallet",
+        "currency": {
+            "asset_id": format!("{}-asset", currency.to_lowercase()),
+            "code": currency,
+            "name": currency,
+        },
+        "balance": {
+            "amount": amount,
+            "currency": currency,
+        },
+        "created_at": "2024-01-01T00:00:00Z",
+        "updated_at": "2024-01-01T00:00:00Z",
+    })
+}
+
+/// Completed transaction, valued in USD (i.e., `transaction("tx-1", "buy", "0.1", "BTC", "5000.00")`)
+pub fn transaction(
+    id: &str,
+    r#type: &str,
+    amount: &str,
+    currency: &str,
+    native_amount: &str,
+) -> Value {
+    json!({
+        "id": id,
+        "type": r#type,
+        "status": "completed",
+        "amount": {
+            "amount": amount,
+            "currency": currency,
+        },
+        "native_amount": {
+            "amount": native_amount,
+            "currency": "USD",
+        },
+        "description": null,
+        "created_at": "2024-01-01T00:00:00Z",
+    })
+}
+
+/// BTC buy with the provided status and creation time (i.e., `transaction_at("tx-1", "pending", "2024-01-02T00:00:00Z")`)
+pub fn transaction_at(id: &str, status: &str, created_at: &str) -> Value {
+    let mut tx: Value = transaction(id, "buy", "0.1", "BTC", "5000.00");
+    tx["status"] = Value::from(status);
+    tx["created_at"] = Value::from(created_at);
+    tx
+}
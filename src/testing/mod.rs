@@ -0,0 +1,6 @@
+//! Testing utilities
+//!
+//! Available with the `testing` feature, to run the integration tests against [`CoinbaseAppClient`](crate::app::client::CoinbaseAppClient) offline.
+
+pub mod fixtures;
+pub mod server;
@@ -0,0 +1,657 @@
+//! In-process mock of the Coinbase App APIs
+//!
+//! Emulates `/v2/accounts`, `/v2/accounts/:id` and `/v2/accounts/:id/transactions`,
+//! with the `next_uri` pagination, the error envelopes, the rate limiting and the JWT verification.
+
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use http_body_util::Full;
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{AUTHORIZATION, CONTENT_TYPE, RETRY_AFTER};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use ring::signature::{ECDSA_P256_SHA256_FIXED, ED25519, UnparsedPublicKey};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use url::{Url, form_urlencoded};
+
+use crate::app::error::Error;
+use crate::util::time;
+
+/// Host of the JWT `uri` claim
+const JWT_HOST: &str = "api.coinbase.com";
+/// Default page size
+const DEFAULT_LIMIT: usize = 25;
+/// Max page size
+const MAX_LIMIT: usize = 100;
+
+/// Public key used to verify the JWTs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JwtPublicKey {
+    /// ECDSA P-256 public key, as uncompressed SEC1 point (`ES256`)
+    Es256(Vec<u8>),
+    /// Ed25519 public key (`EdDSA`)
+    Ed25519(Vec<u8>),
+}
+
+impl JwtPublicKey {
+    fn verify(&self, alg: &str, message: &[u8], signature: &[u8]) -> bool {
+        match (self, alg) {
+            (Self::Es256(key), "ES256") => UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, key)
+                .verify(message, signature)
+                .is_ok(),
+            (Self::Ed25519(key), "EdDSA") => UnparsedPublicKey::new(&ED25519, key)
+                .verify(message, signature)
+                .is_ok(),
+            _ => false,
+        }
+    }
+}
+
+/// Failure returned instead of the next response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MockFailure {
+    /// Error envelope
+    Error {
+        /// HTTP status code
+        status: u16,
+        /// Error ID (i.e., `not_found`)
+        id: String,
+        /// Error message
+        message: String,
+    },
+    /// `429 Too Many Requests`, with the `Retry-After` header (secs)
+    RateLimited {
+        /// Retry after (secs)
+        retry_after: u64,
+    },
+}
+
+/// Mock server builder
+#[derive(Debug, Clone, Default)]
+pub struct MockServerBuilder {
+    /// Verify the JWTs with this key (default: no verification)
+    pub public_key: Option<JwtPublicKey>,
+    /// Max page size, to force the pagination (default: 100)
+    pub page_size: Option<usize>,
+}
+
+impl MockServerBuilder {
+    /// Verify the JWTs with this key
+    #[inline]
+    pub fn public_key(mut self, public_key: JwtPublicKey) -> Self {
+        self.public_key = Some(public_key);
+        self
+    }
+
+    /// Set the max page size, to force the pagination
+    #[inline]
+    pub fn page_size(mut self, page_size: usize) -> Self {
+        self.page_size = Some(page_size);
+        self
+    }
+
+    /// Start the server on a random local port
+    pub async fn start(self) -> Result<MockServer, Error> {
+        let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0))).await?;
+        let url: Url = Url::parse(&format!("http://{}", listener.local_addr()?))?;
+
+        let state = Arc::new(Mutex::new(State {
+            public_key: self.public_key,
+            page_size: self.page_size.unwrap_or(MAX_LIMIT).max(1),
+            ..State::default()
+        }));
+
+        let task = tokio::spawn(serve(listener, state.clone()));
+
+        Ok(MockServer { url, state, task })
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    public_key: Option<JwtPublicKey>,
+    page_size: usize,
+    accounts: Vec<Value>,
+    transactions: HashMap<String, Vec<Value>>,
+    exchange_rates: HashMap<String, Value>,
+    failures: VecDeque<MockFailure>,
+    requests: Vec<String>,
+}
+
+/// Mock Coinbase App server
+///
+/// The server is stopped when dropped.
+///
+/// ```no_run
+/// use coinbase_api::prelude::*;
+///
+/// # async fn run() -> Result<(), Error> {
+/// let server = MockServer::start().await?;
+/// server.add_account(testing::fixtures::account("btc-wallet", "BTC", "0.5"));
+///
+/// let client = CoinbaseAppClient::builder()
+///     .base_url(server.url().clone())
+///     .build()?;
+/// let accounts = client.accounts().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct MockServer {
+    url: Url,
+    state: Arc<Mutex<State>>,
+    task: JoinHandle<()>,
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl MockServer {
+    /// Get a new builder
+    #[inline]
+    pub fn builder() -> MockServerBuilder {
+        MockServerBuilder::default()
+    }
+
+    /// Start a server without JWT verification
+    #[inline]
+    pub async fn start() -> Result<Self, Error> {
+        Self::builder().start().await
+    }
+
+    /// Base URL, to use with [`CoinbaseAppClientBuilder::base_url`](crate::app::builder::CoinbaseAppClientBuilder::base_url)
+    #[inline]
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Add an account (see [`fixtures::account`](super::fixtures::account))
+    pub fn add_account(&self, account: Value) {
+        self.lock().accounts.push(account);
+    }
+
+    /// Add a transaction to an account (see [`fixtures::transaction`](super::fixtures::transaction))
+    ///
+    /// The transactions are listed from the newest (by `created_at`), like the Coinbase API.
+    pub fn add_transaction(&self, account_id: &str, transaction: Value) {
+        let mut state = self.lock();
+        let transactions: &mut Vec<Value> = state
+            .transactions
+            .entry(account_id.to_string())
+            .or_default();
+
+        // After the transactions created at the same time
+        let created_at: &str = transaction["created_at"].as_str().unwrap_or_default();
+        let index: usize = transactions
+            .iter()
+            .position(|tx| tx["created_at"].as_str().unwrap_or_default() < created_at)
+            .unwrap_or(transactions.len());
+        transactions.insert(index, transaction);
+    }
+
+    /// Set the exchange rates of a currency (i.e., `set_exchange_rates("USD", &[("BTC", "0.00002")])`)
+    pub fn set_exchange_rates(&self, currency: &str, rates: &[(&str, &str)]) {
+        let rates: serde_json::Map<String, Value> = rates
+            .iter()
+            .map(|(code, rate)| (code.to_string(), Value::from(*rate)))
+            .collect();
+
+        self.lock()
+            .exchange_rates
+            .insert(currency.to_string(), Value::Object(rates));
+    }
+
+    /// Update the status of a transaction (i.e., `completed`)
+    pub fn set_transaction_status(&self, account_id: &str, transaction_id: &str, status: &str) {
+        let mut state = self.lock();
+
+        let transaction: Option<&mut Value> =
+            state
+                .transactions
+                .get_mut(account_id)
+                .and_then(|transactions| {
+                    transactions
+                        .iter_mut()
+                        .find(|tx| tx["id"] == transaction_id)
+                });
+
+        if let Some(transaction) = transaction {
+            transaction["status"] = Value::from(status);
+        }
+    }
+
+    /// Remove a transaction (i.e., deleted or no longer listed)
+    pub fn remove_transaction(&self, account_id: &str, transaction_id: &str) {
+        if let Some(transactions) = self.lock().transactions.get_mut(account_id) {
+            transactions.retain(|tx| tx["id"] != transaction_id);
+        }
+    }
+
+    /// Fail the next request
+    ///
+    /// The failures are queued: one is returned for every request.
+    pub fn fail_next(&self, failure: MockFailure) {
+        self.lock().failures.push_back(failure);
+    }
+
+    /// Received requests (i.e., `GET /v2/accounts?limit=100`)
+    pub fn requests(&self) -> Vec<String> {
+        self.lock().requests.clone()
+    }
+
+    #[inline]
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().expect("mock server lock poisoned")
+    }
+}
+
+async fn serve(listener: TcpListener, state: Arc<Mutex<State>>) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(..) => continue,
+        };
+
+        let state = state.clone();
+
+        tokio::spawn(async move {
+            let service = service_fn(move |req: Request<Incoming>| {
+                let response = handle(&state, &req);
+                async move { Ok::<_, Infallible>(response) }
+            });
+
+            let _ = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+    }
+}
+
+fn handle(state: &Mutex<State>, req: &Request<Incoming>) -> Response<Full<Bytes>> {
+    let mut state = state.lock().expect("mock server lock poisoned");
+
+    state
+        .requests
+        .push(format!("{} {}", req.method(), req.uri()));
+
+    if let Some(public_key) = &state.public_key {
+        if let Err(why) = verify_jwt(public_key, req) {
+            return error_response(StatusCode::UNAUTHORIZED, "authentication_error", &why);
+        }
+    }
+
+    if let Some(failure) = state.failures.pop_front() {
+        return match failure {
+            MockFailure::Error {
+                status,
+                id,
+                message,
+            } => error_response(
+                StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+                &id,
+                &message,
+            ),
+            MockFailure::RateLimited { retry_after } => {
+                let mut response = error_response(
+                    StatusCode::TOO_MANY_REQUESTS,
+                    "rate_limit_exceeded",
+                    "Too many requests",
+                );
+                response
+                    .headers_mut()
+                    .insert(RETRY_AFTER, retry_after.into());
+                response
+            }
+        };
+    }
+
+    if req.method() != Method::GET {
+        return error_response(StatusCode::NOT_FOUND, "not_found", "Not found");
+    }
+
+    let path: &str = req.uri().path();
+    let query: &str = req.uri().query().unwrap_or_default();
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    match segments.as_slice() {
+        ["v2", "exchange-rates"] => {
+            let currency: String = form_urlencoded::parse(query.as_bytes())
+                .find(|(key, _)| key == "currency")
+                .map(|(_, value)| value.into_owned())
+                .unwrap_or_else(|| String::from("USD"));
+            let rates: Value = state
+                .exchange_rates
+                .get(&currency)
+                .cloned()
+                .unwrap_or_else(|| json!({}));
+
+            json_response(
+                StatusCode::OK,
+                &json!({ "data": { "currency": currency, "rates": rates } }),
+            )
+        }
+        ["v2", "accounts"] => paginate(path, query, &state.accounts, state.page_size),
+        ["v2", "accounts", id] => match find(&state.accounts, id) {
+            Some(account) => json_response(StatusCode::OK, &json!({ "data": account })),
+            None => error_response(StatusCode::NOT_FOUND, "not_found", "Account not found"),
+        },
+        ["v2", "accounts", id, "transactions"] => match find(&state.accounts, id) {
+            Some(..) => {
+                let transactions: &[Value] = state
+                    .transactions
+                    .get(*id)
+                    .map(Vec::as_slice)
+                    .unwrap_or_default();
+                paginate(path, query, transactions, state.page_size)
+            }
+            None => error_response(StatusCode::NOT_FOUND, "not_found", "Account not found"),
+        },
+        ["v2", "accounts", id, "transactions", transaction_id] => {
+            let transaction: Option<&Value> = state
+                .transactions
+                .get(*id)
+                .and_then(|transactions| find(transactions, transaction_id));
+
+            match transaction {
+                Some(transaction) => json_response(StatusCode::OK, &json!({ "data": transaction })),
+                None => error_response(StatusCode::NOT_FOUND, "not_found", "Transaction not found"),
+            }
+        }
+        _ => error_response(StatusCode::NOT_FOUND, "not_found", "Not found"),
+    }
+}
+
+#[derive(Deserialize)]
+struct JwtHeader {
+    alg: String,
+}
+
+#[derive(Deserialize)]
+struct JwtClaims {
+    nbf: u64,
+    exp: u64,
+    uri: Option<String>,
+}
+
+/// Verifies the signature and the claims of the bearer token.
+fn verify_jwt(public_key: &JwtPublicKey, req: &Request<Incoming>) -> Result<(), String> {
+    let token: &str = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or("missing bearer token")?;
+
+    let (message, signature) = token.rsplit_once('.').ok_or("malformed token")?;
+    let (header, claims) = message.split_once('.').ok_or("malformed token")?;
+
+    let header: JwtHeader = decode_part(header)?;
+    let claims: JwtClaims = decode_part(claims)?;
+    let signature: Vec<u8> = URL_SAFE_NO_PAD
+        .decode(signature)
+        .map_err(|e| e.to_string())?;
+
+    if !public_key.verify(&header.alg, message.as_bytes(), &signature) {
+        return Err(String::from("invalid signature"));
+    }
+
+    let now: u64 = time::now();
+    if claims.nbf > now || claims.exp < now {
+        return Err(String::from("token expired or not yet valid"));
+    }
+
+    let expected: String = format!("{} {JWT_HOST}{}", req.method(), req.uri().path());
+    if claims.uri.as_deref() != Some(expected.as_str()) {
+        return Err(format!("unexpected uri claim: {:?}", claims.uri));
+    }
+
+    Ok(())
+}
+
+fn decode_part<T>(part: &str) -> Result<T, String>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    let raw: Vec<u8> = URL_SAFE_NO_PAD.decode(part).map_err(|e| e.to_string())?;
+    serde_json::from_slice(&raw).map_err(|e| e.to_string())
+}
+
+#[inline]
+fn find<'a>(items: &'a [Value], id: &str) -> Option<&'a Value> {
+    items.iter().find(|item| item["id"] == id)
+}
+
+/// Returns a page of items, after the `starting_after` cursor or before the `ending_before` one.
+///
+/// An unknown cursor is answered with a `404`.
+fn paginate(path: &str, query: &str, items: &[Value], page_size: usize) -> Response<Full<Bytes>> {
+    let params: HashMap<String, String> = form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect();
+
+    let limit: usize = params
+        .get("limit")
+        .and_then(|limit| limit.parse().ok())
+        .unwrap_or(DEFAULT_LIMIT)
+        .clamp(1, MAX_LIMIT)
+        .min(page_size);
+
+    let position = |cursor: &str| items.iter().position(|item| item["id"] == cursor);
+
+    let (start, end): (usize, usize) =
+        match (params.get("starting_after"), params.get("ending_before")) {
+            (Some(cursor), _) => match position(cursor) {
+                Some(pos) => (pos + 1, (pos + 1 + limit).min(items.len())),
+                None => {
+                    return error_response(StatusCode::NOT_FOUND, "not_found", "Cursor not found");
+                }
+            },
+            (None, Some(cursor)) => match position(cursor) {
+                Some(pos) => (pos.saturating_sub(limit), pos),
+                None => {
+                    return error_response(StatusCode::NOT_FOUND, "not_found", "Cursor not found");
+                }
+            },
+            (None, None) => (0, limit.min(items.len())),
+        };
+
+    let page: &[Value] = &items[start..end];
+
+    let next_uri: Option<String> = match page.last() {
+        Some(last) if end < items.len() => Some(format!(
+            "{path}?limit={limit}&starting_after={}",
+            last["id"].as_str().unwrap_or_default()
+        )),
+        _ => None,
+    };
+
+    let previous_uri: Option<String> = match page.first() {
+        Some(first) if start > 0 => Some(format!(
+            "{path}?limit={limit}&ending_before={}",
+            first["id"].as_str().unwrap_or_default()
+        )),
+        _ => None,
+    };
+
+    json_response(
+        StatusCode::OK,
+        &json!({
+            "pagination": {
+                "ending_before": params.get("ending_before"),
+                "starting_after": params.get("starting_after"),
+                "limit": limit,
+                "order": "desc",
+                "previous_uri": previous_uri,
+                "next_uri": next_uri,
+            },
+            "data": page,
+        }),
+    )
+}
+
+fn json_response(status: StatusCode, body: &Value) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Full::new(Bytes::from(body.to_string())))
+        .expect("valid response")
+}
+
+#[inline]
+fn error_response(status: StatusCode, id: &str, message: &str) -> Response<Full<Bytes>> {
+    json_response(
+        status,
+        &json!({ "errors": [{ "id": id, "message": message }] }),
+    )
+}
+
+#[cfg(all(test, feature = "reqwest"))]
+mod tests {
+    use std::time::Duration;
+
+    use base64::engine::general_purpose::STANDARD;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    use super::*;
+    use crate::app::auth::CoinbaseAuth;
+    use crate::app::client::CoinbaseAppClient;
+    use crate::testing::fixtures;
+
+    const SEED: [u8; 32] = [7u8; 32];
+
+    fn auth() -> CoinbaseAuth {
+        CoinbaseAuth::ApiKeys {
+            api_key: String::from("organizations/org/apiKeys/key"),
+            secret_key: STANDARD.encode(SEED).into(),
+        }
+    }
+
+    fn public_key() -> JwtPublicKey {
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(&SEED).unwrap();
+        JwtPublicKey::Ed25519(key_pair.public_key().as_ref().to_vec())
+    }
+
+    /// Coinbase error ID
+    fn error_id(error: &Error) -> &str {
+        match error.inner() {
+            Error::Coinbase(e) => &e.id,
+            e => panic!("unexpected error: {e:?}"),
+        }
+    }
+
+    async fn server() -> MockServer {
+        let server = MockServer::builder()
+            .public_key(public_key())
+            .page_size(2)
+            .start()
+            .await
+            .unwrap();
+
+        for (id, currency) in [("btc", "BTC"), ("eth", "ETH"), ("usd", "USD")] {
+            server.add_account(fixtures::account(id, currency, "1.5"));
+        }
+
+        server
+    }
+
+    #[tokio::test]
+    async fn test_pagination() {
+        let server = server().await;
+        for i in 0..5 {
+            let id: String = format!("tx-{i}");
+            server.add_transaction(
+                "btc",
+                fixtures::transaction(&id, "buy", "0.1", "BTC", "5000.00"),
+            );
+        }
+
+        let client = CoinbaseAppClient::builder()
+            .auth(auth())
+            .base_url(server.url().clone())
+            .build()
+            .unwrap();
+
+        let accounts = client.accounts().await.unwrap();
+        let ids: Vec<&str> = accounts.iter().map(|a| a.id.as_str()).collect();
+        assert_eq!(ids, ["btc", "eth", "usd"]);
+
+        let account = client.account("eth").await.unwrap();
+        assert_eq!(account.balance.amount, 1.5);
+
+        let transactions = client.transactions("btc").await.unwrap();
+        assert_eq!(transactions.len(), 5);
+        assert_eq!(transactions[4].id, "tx-4");
+
+        assert_eq!(
+            server.requests()[..2],
+            [
+                "GET /v2/accounts?limit=100",
+                "GET /v2/accounts?limit=2&starting_after=eth"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failures() {
+        let server = server().await;
+
+        let client = CoinbaseAppClient::builder()
+            .auth(auth())
+            .base_url(server.url().clone())
+            .build()
+            .unwrap();
+
+        server.fail_next(MockFailure::RateLimited { retry_after: 1 });
+        let error: Error = client.accounts().await.unwrap_err();
+        assert_eq!(error.status(), Some(429));
+        assert!(error.is_rate_limited());
+        assert_eq!(error.retry_after(), Some(Duration::from_secs(1)));
+        assert_eq!(error_id(&error), "rate_limit_exceeded");
+
+        server.fail_next(MockFailure::Error {
+            status: 500,
+            id: String::from("internal_server_error"),
+            message: String::from("Internal server error"),
+        });
+        let error: Error = client.account("btc").await.unwrap_err();
+        assert_eq!(error.status(), Some(500));
+        assert!(error.is_retryable());
+        assert_eq!(error_id(&error), "internal_server_error");
+
+        let error: Error = client.account("unknown").await.unwrap_err();
+        assert_eq!(error.status(), Some(404));
+        assert!(!error.is_retryable());
+        assert_eq!(error_id(&error), "not_found");
+
+        assert!(client.account("btc").await.is_ok());
+
+        // Wrong key
+        let server = MockServer::builder()
+            .public_key(JwtPublicKey::Ed25519(vec![0u8; 32]))
+            .start()
+            .await
+            .unwrap();
+        let client = CoinbaseAppClient::builder()
+            .auth(auth())
+            .base_url(server.url().clone())
+            .build()
+            .unwrap();
+        let error: Error = client.accounts().await.unwrap_err();
+        assert_eq!(error.status(), Some(401));
+        assert!(error.is_auth());
+        assert_eq!(error_id(&error), "authentication_error");
+    }
+}
@@ -0,0 +1,78 @@
+use std::collections::BTreeMap;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+
+use crate::app::error::Error;
+
+/// Deserializes a JSON body, reporting the path of the failing field.
+pub(crate) fn from_slice<T>(bytes: &[u8]) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+{
+    let deserializer = &mut serde_json::Deserializer::from_slice(bytes);
+    serde_path_to_error::deserialize(deserializer).map_err(Error::from)
+}
+
+/// Deserializes a JSON value, reporting the path of the failing field.
+pub(crate) fn from_value<T>(value: Value) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+{
+    serde_path_to_error::deserialize(value).map_err(Error::from)
+}
+
+/// Deserializes a stringified number (i.e., `"39.59000000"`) into a `f64`.
+pub(crate) fn deserialize_string_to_f64<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s: String = String::deserialize(deserializer)?;
+    s.parse().map_err(serde::de::Error::custom)
+}
+
+/// Deserializes a map of stringified numbers (i.e., `{"BTC": "0.0000163"}`).
+pub(crate) fn deserialize_string_map_to_f64<'de, D>(
+    deserializer: D,
+) -> Result<BTreeMap<String, f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let map: BTreeMap<String, String> = BTreeMap::deserialize(deserializer)?;
+    map.into_iter()
+        .map(|(key, value)| match value.parse() {
+            Ok(value) => Ok((key, value)),
+            Err(e) => Err(serde::de::Error::custom(format!("{key}: {e}"))),
+        })
+        .collect()
+}
+
+/// Coinbase sends some counters either as strings or as numbers.
+#[cfg(feature = "ws")]
+pub(crate) fn deserialize_string_to_u64<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Value::deserialize(deserializer)? {
+        Value::String(s) => s.parse().map_err(serde::de::Error::custom),
+        Value::Number(n) => n
+            .as_u64()
+            .ok_or_else(|| serde::de::Error::custom("expected an unsigned integer")),
+        _ => Err(serde::de::Error::custom("expected a string or a number")),
+    }
+}
+
+/// Coinbase sends empty strings for the values that don't apply (i.e., the limit price of a market order).
+#[cfg(feature = "ws")]
+pub(crate) fn deserialize_optional_string_to_f64<'de, D>(
+    deserializer: D,
+) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<String>::deserialize(deserializer)? {
+        Some(s) if !s.is_empty() => s.parse().map(Some).map_err(serde::de::Error::custom),
+        _ => Ok(None),
+    }
+}
@@ -1 +1,3 @@
+pub(super) mod de;
+pub(super) mod ser;
 pub(super) mod time;
@@ -0,0 +1,24 @@
+use std::collections::BTreeMap;
+
+use serde::Serializer;
+
+/// Serializes a `f64` into a stringified number (i.e., `"39.59"`), like the Coinbase APIs.
+///
+/// The number is written in its shortest form: the padding of the API (i.e., `"39.59000000"`) is lost.
+pub(crate) fn serialize_f64_to_string<S>(value: &f64, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.collect_str(value)
+}
+
+/// Serializes a map of `f64` into a map of stringified numbers, in their shortest form.
+pub(crate) fn serialize_f64_map_to_string<S>(
+    map: &BTreeMap<String, f64>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.collect_map(map.iter().map(|(key, value)| (key, value.to_string())))
+}